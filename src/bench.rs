@@ -0,0 +1,400 @@
+// Throughput measurements for `bench` and `estimate`. Every mode runs the same address
+// computation and scoring as the search, without reporting.
+use crate::conf::config::AppConfig;
+use crate::create2::{self, Create2Context};
+use crate::error::{Error, Result};
+use crate::eth;
+use crate::hd::{MnemonicConfig, MnemonicSearch};
+use crate::keccak;
+use crate::output::OutputFormat;
+use crate::strategy::Strategy;
+use crate::thread;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use serde::Serialize;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tiny_keccak::{Hasher, Keccak};
+
+// Candidates computed together, as in the search.
+const BATCH: usize = 8;
+
+// Time spent on every stage of the per-stage breakdown.
+const STAGE_DURATION: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BenchMode {
+    Eoa,
+    Contract,
+    Create2,
+    Create3,
+    Mnemonic,
+}
+
+impl BenchMode {
+    pub fn parse(mode: &str) -> Option<BenchMode> {
+        match mode {
+            "eoa" => Some(BenchMode::Eoa),
+            "contract" => Some(BenchMode::Contract),
+            "create2" => Some(BenchMode::Create2),
+            "create3" => Some(BenchMode::Create3),
+            "mnemonic" => Some(BenchMode::Mnemonic),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BenchMode::Eoa => "eoa",
+            BenchMode::Contract => "contract",
+            BenchMode::Create2 => "create2",
+            BenchMode::Create3 => "create3",
+            BenchMode::Mnemonic => "mnemonic",
+        }
+    }
+}
+
+/// Speed of one mode, case variant and thread count.
+#[derive(Serialize)]
+pub struct Measurement {
+    pub mode: &'static str,
+    pub casesensitive: bool,
+    pub threads: u32,
+    pub addresses_per_second: u64,
+}
+
+/// Average single threaded time of one stage of computing and scoring an address.
+#[derive(Serialize)]
+pub struct Stage {
+    pub stage: &'static str,
+    pub ns_per_op: f64,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub version: &'static str,
+    pub keccak_backend: &'static str,
+    pub seconds: u64,
+    pub measurements: Vec<Measurement>,
+    pub stages: Vec<Stage>,
+}
+
+/// Number of addresses per second `threads` threads compute and score in `mode` over
+/// `duration`, scored case sensitively when `casesensitive` is set.
+pub fn measure(mode: BenchMode, casesensitive: bool, threads: u32, duration: Duration) -> u64 {
+    let stop = Arc::new(AtomicBool::new(false));
+    let processed = Arc::new(AtomicU64::new(0));
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let stop = stop.clone();
+            let processed = processed.clone();
+            std::thread::spawn(move || work(mode, casesensitive, &stop, &processed))
+        })
+        .collect();
+
+    let start = Instant::now();
+    std::thread::sleep(duration);
+    stop.store(true, Ordering::Relaxed);
+    let count = processed.load(Ordering::Relaxed);
+    let elapsed = start.elapsed().as_secs_f64();
+    for worker in workers {
+        _ = worker.join();
+    }
+    (count as f64 / elapsed) as u64
+}
+
+// Scoring config of the benchmark, a prefix that rarely matches so that the whole pattern is
+// compared most of the time.
+fn bench_config(casesensitive: bool) -> AppConfig {
+    let pattern = match casesensitive {
+        true => "dEaDbEeF",
+        false => "deadbeef",
+    };
+    AppConfig::with_pattern(pattern.to_string(), Strategy::Startswith, casesensitive)
+}
+
+fn work(mode: BenchMode, casesensitive: bool, stop: &AtomicBool, processed: &AtomicU64) {
+    let mut rng = crate::entropy::EntropySource::Os.rng();
+    let config = bench_config(casesensitive);
+    let hash = match mode {
+        BenchMode::Create3 => create2::bytecode_keccak(create2::CREATE3_PROXY_BYTECODE),
+        _ => create2::bytecode_keccak(&[]),
+    };
+    // the same batches the search hashes together
+    let mut create2_context = Create2Context::new(
+        &[0; 20],
+        hash,
+        mode == BenchMode::Create3,
+        create2::generate_salt(&mut rng),
+        BATCH,
+    );
+    let mut mnemonic_search = MnemonicSearch::new(&MnemonicConfig {
+        words: 12,
+        indexes: 0..1,
+    });
+
+    while !stop.load(Ordering::Relaxed) {
+        let addresses = match mode {
+            BenchMode::Eoa => eth::generate_wallets(&mut rng, BATCH)
+                .into_iter()
+                .map(|wallet| wallet.public_key)
+                .collect(),
+            BenchMode::Contract => {
+                eth::generate_contract_addresses_at(&eth::generate_wallets(&mut rng, BATCH), 0)
+            }
+            BenchMode::Create2 | BenchMode::Create3 => create2_context
+                .next_batch()
+                .iter()
+                .map(hex::encode)
+                .collect(),
+            BenchMode::Mnemonic => {
+                mnemonic_search.next_mnemonic(&mut rng);
+                match mnemonic_search.wallet_at(0) {
+                    Some(wallet) => vec![wallet.public_key],
+                    None => continue,
+                }
+            }
+        };
+        for address in &addresses {
+            black_box(thread::score_address(&config, address));
+        }
+        processed.fetch_add(addresses.len() as u64, Ordering::Relaxed);
+    }
+}
+
+// Average time of `op` in nanoseconds, run for `duration`.
+fn time_stage(duration: Duration, mut op: impl FnMut()) -> f64 {
+    let start = Instant::now();
+    let mut count = 0u64;
+    while start.elapsed() < duration {
+        for _ in 0..256 {
+            op();
+        }
+        count += 256;
+    }
+    start.elapsed().as_nanos() as f64 / count as f64
+}
+
+type StageOp<'a> = (&'static str, Box<dyn FnMut() + 'a>);
+
+/// Times the stages an EOA address goes through: public key derivation, hashing the public
+/// key, hex encoding, EIP-55 checksumming and scoring against a pattern.
+pub fn measure_stages(duration: Duration) -> Vec<Stage> {
+    let context = Secp256k1::signing_only();
+    let secret_key = SecretKey::from_slice(&[0x42; 32]).expect("Valid secret key");
+    let public_key = PublicKey::from_secret_key(&context, &secret_key);
+    let serialized = public_key.serialize_uncompressed();
+    let address = eth::public_key_address(&public_key);
+    let config = bench_config(false);
+
+    let stages: [StageOp; 5] = [
+        (
+            "ec_multiply",
+            Box::new(|| {
+                black_box(PublicKey::from_secret_key(&context, black_box(&secret_key)));
+            }),
+        ),
+        (
+            "keccak",
+            Box::new(|| {
+                let mut keccak = Keccak::v256();
+                let mut hash = [0u8; 32];
+                keccak.update(black_box(&serialized[1..]));
+                keccak.finalize(&mut hash);
+                black_box(hash);
+            }),
+        ),
+        (
+            "hex",
+            Box::new(|| {
+                black_box(hex::encode(black_box(&serialized[45..])));
+            }),
+        ),
+        (
+            "checksum",
+            Box::new(|| {
+                black_box(eth::checksum(black_box(&address)));
+            }),
+        ),
+        (
+            "score",
+            Box::new(|| {
+                black_box(thread::score_address(&config, black_box(&address)));
+            }),
+        ),
+    ];
+    let mut stages: Vec<Stage> = stages
+        .into_iter()
+        .map(|(stage, op)| Stage {
+            stage,
+            ns_per_op: time_stage(duration, op),
+        })
+        .collect();
+    // per message, hashing a batch with the multi-lane backend
+    let messages = [&serialized[1..]; BATCH];
+    let batch = time_stage(duration, || {
+        black_box(keccak::keccak256_batch(black_box(&messages)));
+    });
+    stages.insert(
+        2,
+        Stage {
+            stage: "keccak_batch",
+            ns_per_op: batch / BATCH as f64,
+        },
+    );
+    stages
+}
+
+// 1, 2, 4, ... up to and including `max`.
+fn thread_counts(max: u32) -> Vec<u32> {
+    let mut counts: Vec<u32> = std::iter::successors(Some(1u32), |n| n.checked_mul(2))
+        .take_while(|&n| n < max)
+        .collect();
+    counts.push(max.max(1));
+    counts
+}
+
+/// Measures every mode with case insensitive and case sensitive scoring for 1, 2, 4, ... up
+/// to `threads` threads, followed by the per-stage breakdown.
+pub fn run(modes: &[BenchMode], threads: u32, seconds: u64, format: OutputFormat) -> Result<()> {
+    let text = format == OutputFormat::Text;
+    let counts = thread_counts(threads);
+    if text {
+        println!(
+            "Measuring {} for {}s each with {:?} threads.",
+            modes
+                .iter()
+                .map(BenchMode::name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            seconds,
+            counts
+        );
+        println!("Keccak backend: {}", keccak::backend().name());
+        println!(
+            "{:<10} {:<12} {:>7} {:>14}",
+            "mode", "case", "threads", "addresses/s"
+        );
+    }
+
+    let mut measurements = vec![];
+    for &mode in modes {
+        for casesensitive in [false, true] {
+            for &threads in &counts {
+                let speed = measure(mode, casesensitive, threads, Duration::from_secs(seconds));
+                if text {
+                    let case = match casesensitive {
+                        true => "sensitive",
+                        false => "insensitive",
+                    };
+                    println!(
+                        "{:<10} {:<12} {:>7} {:>14}",
+                        mode.name(),
+                        case,
+                        threads,
+                        speed
+                    );
+                }
+                measurements.push(Measurement {
+                    mode: mode.name(),
+                    casesensitive,
+                    threads,
+                    addresses_per_second: speed,
+                });
+            }
+        }
+    }
+
+    let stages = measure_stages(STAGE_DURATION);
+    if text {
+        println!();
+        println!("{:<12} {:>10}", "stage", "ns/op");
+        for stage in &stages {
+            println!("{:<12} {:>10.1}", stage.stage, stage.ns_per_op);
+        }
+        return Ok(());
+    }
+
+    let report = Report {
+        version: env!("CARGO_PKG_VERSION"),
+        keccak_backend: keccak::backend().name(),
+        seconds,
+        measurements,
+        stages,
+    };
+    let json =
+        serde_json::to_string_pretty(&report).map_err(|e| Error::Io(std::io::Error::other(e)))?;
+    println!("{}", json);
+    Ok(())
+}
+
+/// Prints the expected number of attempts for `config` and how long they take at `speed`,
+/// measuring the speed of `mode` first if it is not given.
+pub fn estimate(config: &AppConfig, mode: BenchMode, speed: Option<u64>) -> Result<()> {
+    if config.strategy == Strategy::Trailing {
+        return Err(Error::Config(
+            "The trailing strategy has no complete match to estimate".to_string(),
+        ));
+    }
+    let attempts = thread::expected_attempts(config);
+    println!("Difficulty: 1 in {}", attempts);
+
+    let speed = speed.unwrap_or_else(|| {
+        println!(
+            "Measuring {} speed with {} threads...",
+            mode.name(),
+            config.threads
+        );
+        measure(
+            mode,
+            config.casesensitive,
+            config.threads,
+            Duration::from_secs(2),
+        )
+    });
+    println!("Speed: {} addresses/s", speed);
+    if speed == 0 {
+        return Ok(());
+    }
+
+    // the number of attempts until a match is geometrically distributed
+    let time = |probability: f64| -> u64 {
+        (-(1.0 - probability).ln() * attempts as f64 / speed as f64).ceil() as u64
+    };
+    println!("Expected time: {}s", attempts / speed);
+    println!("50% probability: {}s", time(0.5));
+    println!("90% probability: {}s", time(0.9));
+    println!("99% probability: {}s", time(0.99));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_counts() {
+        assert_eq!(thread_counts(1), vec![1]);
+        assert_eq!(thread_counts(4), vec![1, 2, 4]);
+        assert_eq!(thread_counts(6), vec![1, 2, 4, 6]);
+    }
+
+    #[test]
+    fn test_stages() {
+        let stages = measure_stages(Duration::from_millis(10));
+        let names: Vec<_> = stages.iter().map(|stage| stage.stage).collect();
+        assert_eq!(
+            names,
+            [
+                "ec_multiply",
+                "keccak",
+                "keccak_batch",
+                "hex",
+                "checksum",
+                "score"
+            ]
+        );
+        assert!(stages.iter().all(|stage| stage.ns_per_op > 0.0));
+    }
+}
@@ -1,6 +1,45 @@
-use ranityeth_lib::conf::config::get_config;
+use ranityeth_lib::conf::config::{get_command, Command};
+use ranityeth_lib::error::Result;
 
 fn main() {
-    let config = get_config();
-    ranityeth_lib::thread::run(config)
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn run() -> Result<()> {
+    match get_command()? {
+        Command::Search(config) => ranityeth_lib::thread::run(*config),
+        Command::Estimate {
+            config,
+            mode,
+            speed,
+        } => ranityeth_lib::bench::estimate(&config, mode, speed),
+        Command::Bench {
+            modes,
+            threads,
+            seconds,
+            output_format,
+        } => ranityeth_lib::bench::run(&modes, threads, seconds, output_format),
+        Command::Results {
+            store,
+            filter,
+            output_format,
+            export,
+        } => ranityeth_lib::store::run(&store, &filter, output_format, export.as_deref()),
+        Command::Verify {
+            path,
+            password,
+            address,
+            show_private_key,
+        } => {
+            ranityeth_lib::keystore::verify(&path, &password, address.as_deref(), show_private_key)
+        }
+        Command::Combine {
+            offset,
+            private_key,
+            address,
+        } => ranityeth_lib::splitkey::run_combine(&offset, &private_key, address.as_deref()),
+    }
 }
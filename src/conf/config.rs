@@ -1,5 +1,21 @@
-use super::parser;
+use super::{parser, profile};
+use crate::bench::BenchMode;
+use crate::create2;
+use crate::entropy::EntropySource;
+use crate::error::{Error, Result};
+use crate::eth;
+use crate::hd::MnemonicConfig;
+use crate::hooks;
+use crate::keystore::{Kdf, KeystoreConfig, PasswordSource};
+use crate::output::OutputFormat;
+use crate::sched;
+use crate::splitkey;
+use crate::store::Filter;
 use crate::strategy::Strategy;
+use crate::utils;
+use clap::Parser;
+use secp256k1::PublicKey;
+use std::ops::Range;
 
 #[derive(Clone)]
 pub struct AppConfig {
@@ -8,61 +24,513 @@ pub struct AppConfig {
     pub casesensitive: bool,
     pub contract: bool,
     pub create2: bool,
+    pub create3: bool,
     pub threads: u32,
+    /// Pin every worker thread to its own core.
+    pub pin_cores: bool,
+    /// Lower the OS priority of the workers.
+    pub nice: bool,
     pub continuous: bool,
-    pub deployer: String,
-    pub bytecode: String,
+    /// Report every address scoring at least this much.
+    pub min_score: Option<u64>,
+    /// Stop after this many reported results.
+    pub max_results: Option<u64>,
+    /// Size of the leaderboard shown when the search ends.
+    pub top: Option<usize>,
+    /// Result store every reported result is added to.
+    pub store: Option<String>,
+    pub deployer: [u8; 20],
+    pub bytecode: Vec<u8>,
+    /// Fixed leading bytes of every CREATE2 salt.
+    pub salt_prefix: Vec<u8>,
     pub pkstorage_path: String,
+    /// Size in bytes at which the output file is rotated.
+    pub max_file_size: Option<u64>,
+    pub hook_flags: u16,
+    pub nonces: Range<u64>,
+    pub eoa_pattern: Option<String>,
+    pub eoa_strategy: Strategy,
+    pub output_format: OutputFormat,
+    /// No progress output.
+    pub quiet: bool,
+    /// Keep secrets off stdout, they are only written to the output file.
+    pub hide_secrets: bool,
+    pub keystore: Option<KeystoreConfig>,
+    pub split_key: Option<PublicKey>,
+    pub mnemonic: Option<MnemonicConfig>,
+    pub entropy: EntropySource,
 }
 
-pub fn get_config() -> AppConfig {
-    let mut args = parser::parse();
-    let strategy = match args.strategy.as_str() {
-        "contains" => Strategy::Contains,
-        "startswith" => Strategy::Startswith,
-        "trailing" => Strategy::Trailing,
-        _ => panic!("Invalid strategy"),
-    };
-    if strategy == Strategy::Trailing && args.pattern.len() != 1 {
-        panic!("Trailing strategy only accepts a single character pattern");
+impl AppConfig {
+    /// Config of a search for an already normalized pattern with every other option at its
+    /// default.
+    pub fn with_pattern(pattern: String, strategy: Strategy, casesensitive: bool) -> AppConfig {
+        AppConfig {
+            pattern,
+            strategy: strategy.clone(),
+            casesensitive,
+            contract: false,
+            create2: false,
+            create3: false,
+            threads: 1,
+            pin_cores: false,
+            nice: false,
+            continuous: false,
+            min_score: None,
+            max_results: None,
+            top: None,
+            store: None,
+            deployer: [0; 20],
+            bytecode: vec![],
+            salt_prefix: vec![],
+            pkstorage_path: String::new(),
+            max_file_size: None,
+            hook_flags: 0,
+            nonces: 0..1,
+            eoa_pattern: None,
+            eoa_strategy: strategy,
+            output_format: OutputFormat::Text,
+            quiet: false,
+            hide_secrets: false,
+            keystore: None,
+            split_key: None,
+            mnemonic: None,
+            entropy: EntropySource::Os,
+        }
+    }
+
+    /// Name of the search, as in the subcommands.
+    pub fn mode(&self) -> &'static str {
+        if self.create3 {
+            "create3"
+        } else if self.create2 {
+            "create2"
+        } else if self.contract {
+            "contract"
+        } else if self.mnemonic.is_some() {
+            "mnemonic"
+        } else {
+            "eoa"
+        }
     }
 
-    if args.create2 {
-        args.contract = true;
-        args.deployer = args.deployer.replace("0x", "");
-        // deployer cannot be empty
-        assert!(
-            !args.deployer.is_empty(),
-            "Deployer address cannot be empty"
-        );
-
-        // bytecode cannot be empty
-        assert!(!args.bytecode.is_empty(), "Bytecode cannot be empty");
-
-        // bytecode length must be greater than 32 bytes
-        assert!(
-            args.bytecode.len() >= 64,
-            "Bytecode length must be greater than 32 bytes"
-        );
-
-        assert!(args.deployer.len() == 40, "Invalid deployer address");
-        // assert!(utils::is_possible_pattern(_addr.as_str()));
+    /// Config used to score the deployer address when both the deployer and its contract
+    /// have to match.
+    pub fn eoa_config(&self) -> Option<AppConfig> {
+        self.eoa_pattern.as_ref().map(|pattern| AppConfig {
+            pattern: pattern.clone(),
+            strategy: self.eoa_strategy.clone(),
+            eoa_pattern: None,
+            ..self.clone()
+        })
+    }
+}
+
+pub enum Command {
+    Search(Box<AppConfig>),
+    Estimate {
+        config: Box<AppConfig>,
+        mode: BenchMode,
+        speed: Option<u64>,
+    },
+    Bench {
+        modes: Vec<BenchMode>,
+        threads: u32,
+        seconds: u64,
+        output_format: OutputFormat,
+    },
+    Results {
+        store: String,
+        filter: Filter,
+        output_format: OutputFormat,
+        export: Option<String>,
+    },
+    Verify {
+        path: String,
+        password: PasswordSource,
+        address: Option<String>,
+        show_private_key: bool,
+    },
+    Combine {
+        offset: String,
+        private_key: PasswordSource,
+        address: Option<String>,
+    },
+}
+
+/// Parses the process arguments together with the config file and `RANITYETH_*` environment
+/// variables, exiting with clap's usage message if they do not parse.
+pub fn get_command() -> Result<Command> {
+    let args = profile::expand_args(std::env::args_os().collect(), |name| {
+        std::env::var(name).ok()
+    })?;
+    build_command(parser::parse_from(args))
+}
+
+/// Parses `args` (including the program name) without touching the process.
+pub fn parse_command<I, T>(args: I) -> Result<Command>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let args = args.into_iter().map(Into::into).collect();
+    let args = profile::expand_args(args, |_| None)?;
+    let args = parser::Args::try_parse_from(args).map_err(|e| Error::Config(e.to_string()))?;
+    build_command(args)
+}
+
+fn build_command(args: parser::Args) -> Result<Command> {
+    let command = match args.command {
+        parser::Command::Eoa {
+            search,
+            key,
+            mnemonic,
+            mnemonic_words,
+            hd_index_range,
+        } => {
+            let mut config = search_config(search)?;
+            ensure(
+                config.strategy != Strategy::Hook,
+                "Hook strategy only applies to contract addresses",
+            )?;
+            key_config(&mut config, key)?;
+            if mnemonic {
+                config.mnemonic = Some(mnemonic_config(mnemonic_words, hd_index_range)?);
+            }
+            Command::Search(Box::new(config))
+        }
+        parser::Command::Contract {
+            search,
+            key,
+            hooks,
+            nonce,
+            nonce_range,
+            eoa_pattern,
+            eoa_strategy,
+        } => {
+            let mut config = search_config(search)?;
+            config.contract = true;
+            key_config(&mut config, key)?;
+            hook_config(&mut config, hooks)?;
+            config.nonces = match (nonce, &nonce_range) {
+                (Some(nonce), _) => {
+                    nonce
+                        ..nonce.checked_add(1).ok_or_else(|| {
+                            Error::Config(format!("Nonce {} is out of range", nonce))
+                        })?
+                }
+                (None, Some(range)) => parse_nonce_range(range).map_err(Error::Config)?,
+                (None, None) => 0..1,
+            };
+            if let Some(eoa_pattern) = eoa_pattern {
+                eoa_config(&mut config, eoa_pattern, eoa_strategy)?;
+            }
+            Command::Search(Box::new(config))
+        }
+        parser::Command::Create2 {
+            search,
+            hooks,
+            deployer,
+            bytecode,
+            salt_prefix,
+        } => {
+            let mut config = search_config(search)?;
+            salt_config(&mut config, hooks, &deployer, salt_prefix.as_deref())?;
+            config.bytecode = hex::decode(bytecode.trim_start_matches("0x"))
+                .map_err(|e| Error::Input(format!("Bytecode is not valid hex: {}", e)))?;
+            ensure(
+                config.bytecode.len() >= 32,
+                "Bytecode length must be greater than 32 bytes",
+            )?;
+            Command::Search(Box::new(config))
+        }
+        parser::Command::Create3 {
+            search,
+            hooks,
+            deployer,
+            salt_prefix,
+        } => {
+            let mut config = search_config(search)?;
+            salt_config(&mut config, hooks, &deployer, salt_prefix.as_deref())?;
+            config.create3 = true;
+            Command::Search(Box::new(config))
+        }
+        parser::Command::Estimate {
+            pattern,
+            speed,
+            mode,
+            threads,
+        } => {
+            let mut config = pattern_config(pattern)?;
+            config.threads = threads.unwrap_or_else(sched::available_threads);
+            Command::Estimate {
+                config: Box::new(config),
+                mode: parse_mode(&mode)?,
+                speed,
+            }
+        }
+        parser::Command::Bench {
+            mode,
+            threads,
+            seconds,
+            output_format,
+        } => Command::Bench {
+            modes: mode
+                .iter()
+                .map(|mode| parse_mode(mode))
+                .collect::<Result<_>>()?,
+            threads: threads.unwrap_or_else(sched::available_threads),
+            seconds,
+            output_format: OutputFormat::parse(&output_format).ok_or_else(|| {
+                Error::Config(format!("Invalid output format: {}", output_format))
+            })?,
+        },
+        parser::Command::Results {
+            store,
+            pattern,
+            min_score,
+            mode,
+            limit,
+            output_format,
+            export,
+        } => Command::Results {
+            store,
+            filter: Filter {
+                pattern,
+                min_score,
+                mode,
+                limit,
+            },
+            output_format: OutputFormat::parse(&output_format).ok_or_else(|| {
+                Error::Config(format!("Invalid output format: {}", output_format))
+            })?,
+            export,
+        },
+        parser::Command::Verify {
+            path,
+            address,
+            show_private_key,
+            password,
+        } => Command::Verify {
+            path,
+            password: password_source(&password),
+            address,
+            show_private_key,
+        },
+        parser::Command::Combine {
+            offset,
+            address,
+            private_key_env,
+            private_key_file,
+        } => Command::Combine {
+            offset,
+            private_key: password_source(&parser::PasswordArgs {
+                password_env: private_key_env,
+                password_file: private_key_file,
+            }),
+            address,
+        },
+    };
+    Ok(command)
+}
+
+fn ensure(condition: bool, message: &str) -> Result<()> {
+    match condition {
+        true => Ok(()),
+        false => Err(Error::Config(message.to_string())),
     }
+}
 
-    if args.continuous && strategy == Strategy::Trailing {
-        panic!("Continuous mode is not supported with trailing strategy");
+fn password_source(args: &parser::PasswordArgs) -> PasswordSource {
+    match (&args.password_env, &args.password_file) {
+        (Some(var), _) => PasswordSource::Env(var.clone()),
+        (None, Some(path)) => PasswordSource::File(path.clone()),
+        (None, None) => PasswordSource::Prompt,
     }
+}
+
+// Config of a search for the pattern with every other option at its default.
+fn pattern_config(args: parser::PatternArgs) -> Result<AppConfig> {
+    let strategy = parse_strategy(&args.strategy)?;
+    let pattern = utils::parse_pattern(&args.pattern, &strategy, args.casesensitive)?;
 
-    AppConfig {
-        pattern: args.pattern,
+    Ok(AppConfig::with_pattern(
+        pattern,
         strategy,
-        casesensitive: args.casesensitive,
-        contract: args.contract,
-        create2: args.create2,
-        threads: args.threads as u32,
-        continuous: args.continuous,
-        deployer: args.deployer,
-        bytecode: args.bytecode,
-        pkstorage_path: args.pkstorage_path,
+        args.casesensitive,
+    ))
+}
+
+fn search_config(args: parser::SearchArgs) -> Result<AppConfig> {
+    let mut config = pattern_config(args.pattern)?;
+    ensure(
+        !args.continuous || config.strategy != Strategy::Trailing || args.min_score.is_some(),
+        "Continuous mode needs --min-score with trailing strategy",
+    )?;
+
+    config.output_format = OutputFormat::parse(&args.output_format)
+        .ok_or_else(|| Error::Config(format!("Invalid output format: {}", args.output_format)))?;
+
+    config.entropy = EntropySource::parse(&args.entropy)
+        .ok_or_else(|| Error::Config(format!("Invalid entropy source: {}", args.entropy)))?;
+    // keys derived from a fixed seed are public knowledge
+    ensure(
+        !config.entropy.is_deterministic(),
+        "Seeded entropy is deterministic and only available for tests, refusing to output keys",
+    )?;
+
+    config.quiet = args.quiet;
+    config.hide_secrets = args.no_print_secrets;
+    config.threads = args.threads.unwrap_or_else(sched::available_threads);
+    config.pin_cores = args.pin_cores;
+    config.nice = args.nice;
+    config.continuous = args.continuous;
+    config.min_score = args.min_score;
+    config.max_results = args.max_results;
+    config.top = args.top.map(|top| top as usize);
+    config.store = args.store;
+    config.pkstorage_path = args.pkstorage_path;
+    config.max_file_size = args.max_file_size.as_deref().map(parse_size).transpose()?;
+    Ok(config)
+}
+
+// A size in bytes with an optional K, M or G suffix (powers of 1024).
+fn parse_size(size: &str) -> Result<u64> {
+    let invalid = || Error::Config(format!("Invalid size: {}", size));
+    let (digits, shift) = match size.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&size[..size.len() - 1], 10),
+        Some('M') => (&size[..size.len() - 1], 20),
+        Some('G') => (&size[..size.len() - 1], 30),
+        _ => (size, 0),
+    };
+    let value: u64 = digits.parse().map_err(|_| invalid())?;
+    match value.checked_mul(1 << shift) {
+        Some(bytes) if bytes > 0 => Ok(bytes),
+        _ => Err(invalid()),
+    }
+}
+
+fn key_config(config: &mut AppConfig, args: parser::KeyArgs) -> Result<()> {
+    if let Some(public_key) = &args.public_key {
+        config.split_key = Some(splitkey::parse_public_key(public_key)?);
+    }
+
+    if let Some(dir) = args.keystore_dir {
+        let kdf = Kdf::parse(&args.keystore_kdf)
+            .ok_or_else(|| Error::Config(format!("Invalid keystore kdf: {}", args.keystore_kdf)))?;
+        let password = password_source(&args.password)
+            .read_new()
+            .map_err(Error::Keystore)?;
+        config.keystore = Some(KeystoreConfig { dir, kdf, password });
+    }
+    Ok(())
+}
+
+fn hook_config(config: &mut AppConfig, args: parser::HookArgs) -> Result<()> {
+    if config.strategy == Strategy::Hook {
+        // hook permissions are read from the address of the deployed hook contract
+        let hooks = args
+            .hooks
+            .ok_or_else(|| Error::Config("Hook strategy requires --hooks".to_string()))?;
+        config.hook_flags = hooks::parse_hook_flags(&hooks).map_err(Error::Config)?;
+    }
+    Ok(())
+}
+
+// Shared by create2 and create3, where the result is a salt for `deployer`.
+fn salt_config(
+    config: &mut AppConfig,
+    hooks: parser::HookArgs,
+    deployer: &str,
+    salt_prefix: Option<&str>,
+) -> Result<()> {
+    config.contract = true;
+    config.create2 = true;
+    config.deployer = eth::parse_address(deployer)?;
+    if let Some(salt_prefix) = salt_prefix {
+        config.salt_prefix = hex::decode(salt_prefix.trim_start_matches("0x"))
+            .map_err(|e| Error::Input(format!("Salt prefix is not valid hex: {}", e)))?;
+        create2::check_salt_prefix(&config.salt_prefix)?;
+    }
+    hook_config(config, hooks)
+}
+
+fn eoa_config(
+    config: &mut AppConfig,
+    eoa_pattern: String,
+    eoa_strategy: Option<String>,
+) -> Result<()> {
+    if let Some(eoa_strategy) = eoa_strategy {
+        config.eoa_strategy = parse_strategy(&eoa_strategy)?;
+    }
+    ensure(
+        config.eoa_strategy != Strategy::Hook,
+        "Hook strategy cannot be used for the EOA, set --eoa-strategy",
+    )?;
+    let eoa_pattern =
+        utils::parse_pattern(&eoa_pattern, &config.eoa_strategy, config.casesensitive)
+            .map_err(|e| Error::Config(e.to_string().replacen("Pattern", "EOA pattern", 1)))?;
+    if config.eoa_strategy == Strategy::Trailing {
+        ensure(
+            !config.continuous || config.min_score.is_some(),
+            "Continuous mode needs --min-score with trailing strategy",
+        )?;
+    }
+    config.eoa_pattern = Some(eoa_pattern);
+    Ok(())
+}
+
+fn mnemonic_config(words: usize, index_range: Option<String>) -> Result<MnemonicConfig> {
+    ensure(
+        [12, 15, 18, 21, 24].contains(&words),
+        "Mnemonics have 12, 15, 18, 21 or 24 words",
+    )?;
+    let indexes = match &index_range {
+        Some(range) => parse_range(range, "index").map_err(Error::Config)?,
+        None => 0..1,
+    };
+    ensure(indexes.end <= 1 << 31, "Address indexes must be below 2^31")?;
+    Ok(MnemonicConfig {
+        words,
+        indexes: indexes.start as u32..indexes.end as u32,
+    })
+}
+
+fn parse_strategy(strategy: &str) -> Result<Strategy> {
+    match strategy {
+        "contains" => Ok(Strategy::Contains),
+        "startswith" => Ok(Strategy::Startswith),
+        "trailing" => Ok(Strategy::Trailing),
+        "hook" => Ok(Strategy::Hook),
+        _ => Err(Error::Config(format!("Invalid strategy: {}", strategy))),
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<BenchMode> {
+    BenchMode::parse(mode).ok_or_else(|| Error::Config(format!("Invalid mode: {}", mode)))
+}
+
+/// Parses `a..b` (exclusive) or `a..=b` (inclusive) into a nonce range.
+pub fn parse_nonce_range(range: &str) -> std::result::Result<Range<u64>, String> {
+    parse_range(range, "nonce")
+}
+
+fn parse_range(range: &str, what: &str) -> std::result::Result<Range<u64>, String> {
+    let invalid = || format!("Invalid {} range: {}", what, range);
+    let (start, end, inclusive) = match range.split_once("..=") {
+        Some((start, end)) => (start, end, true),
+        None => {
+            let (start, end) = range.split_once("..").ok_or_else(invalid)?;
+            (start, end, false)
+        }
+    };
+    let start: u64 = start.trim().parse().map_err(|_| invalid())?;
+    let mut end: u64 = end.trim().parse().map_err(|_| invalid())?;
+    if inclusive {
+        end = end.checked_add(1).ok_or_else(invalid)?;
+    }
+    if start >= end {
+        return Err(format!("Range is empty: {}", range));
     }
+    Ok(start..end)
 }
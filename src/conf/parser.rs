@@ -1,48 +1,388 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+const STRATEGIES: [&str; 4] = ["contains", "startswith", "trailing", "hook"];
+const MODES: [&str; 5] = ["eoa", "contract", "create2", "create3", "mnemonic"];
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 pub(crate) struct Args {
+    /// TOML file with default options, the command line and RANITYETH_* environment variables
+    /// take precedence
+    #[clap(long, value_parser, global = true)]
+    pub config: Option<String>,
+
+    /// Profile of the config file whose options override the top level ones
+    #[clap(long, value_parser, global = true)]
+    pub profile: Option<String>,
+
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct PatternArgs {
     /// The pattern to look for
-    #[clap(short, long, value_parser)]
+    #[clap(short, long, value_parser, default_value = "")]
     pub pattern: String,
 
-    /// "contains", "startswith" or "trailing"
-    #[clap(short, long, value_parser)]
+    /// How the pattern is matched, "hook" only applies to contract addresses
+    #[clap(short, long, value_parser = STRATEGIES)]
     pub strategy: String,
 
     /// Whether the pattern is case sensitive
     #[clap(short, long, value_parser, default_value_t = false)]
     pub casesensitive: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct SearchArgs {
+    #[clap(flatten)]
+    pub pattern: PatternArgs,
 
-    /// Search for a contract address
+    /// Number of threads to use, defaults to the available parallelism
+    #[clap(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Pin every thread to its own core
     #[clap(long, value_parser, default_value_t = false)]
-    pub contract: bool,
+    pub pin_cores: bool,
 
-    /// Number of threads to use
-    #[clap(short, long, value_parser, default_value_t = 1)]
-    pub threads: u8,
+    /// Run with low OS priority (nice 10) so the search can stay in the background
+    #[clap(long, value_parser, default_value_t = false)]
+    pub nice: bool,
 
     /// Continuous mode
     #[clap(long, value_parser, default_value_t = false)]
     pub continuous: bool,
 
-    /// Calculate the deployment address using create2, must set bytecode and deployer address.
-    #[clap(long, value_parser, default_value_t = false)]
-    pub create2: bool,
+    /// Report every address scoring at least this much instead of only improvements
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub min_score: Option<u64>,
+
+    /// Stop after reporting this many results
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_results: Option<u64>,
+
+    /// Keep the best N addresses, near misses included, and show them when the search ends
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..=10000))]
+    pub top: Option<u32>,
+
+    /// SQLite file every result is also stored in, once per address
+    #[clap(long, value_parser)]
+    pub store: Option<String>,
 
-    /// Bytecode of the contract for create2
-    #[clap(long, value_parser, default_value = "")]
-    pub bytecode: String,
+    /// Randomness source for keys and salts: "os" (getrandom for every key) or "chacha"
+    /// (ChaCha20 reseeded from the OS)
+    #[clap(long, value_parser, default_value = "os")]
+    pub entropy: String,
 
-    /// Deployer address for create2
-    #[clap(long, value_parser, default_value = "")]
-    pub deployer: String,
+    /// Output format for results: "text", "json", "ndjson" or "csv"
+    #[clap(long, value_parser, default_value = "text")]
+    pub output_format: String,
+
+    /// Do not show progress on stderr
+    #[clap(short, long, value_parser, default_value_t = false)]
+    pub quiet: bool,
+
+    /// Print results without private keys, offsets and mnemonics, which are only written to
+    /// the output file (and keystores)
+    #[clap(long, value_parser, default_value_t = false)]
+    pub no_print_secrets: bool,
 
+    /// File results are appended to in continuous mode
     #[clap(long, value_parser, default_value = "pks.txt")]
     pub pkstorage_path: String,
+
+    /// Move the output file to <PATH>.1, <PATH>.2, ... once it reaches this size, e.g. 10M
+    #[clap(long, value_parser)]
+    pub max_file_size: Option<String>,
+}
+
+/// Options of searches that generate keys.
+#[derive(clap::Args, Debug)]
+pub(crate) struct KeyArgs {
+    /// Search offsets for this public key instead of generating private keys (split-key mode)
+    #[clap(long, value_parser, conflicts_with = "keystore-dir")]
+    pub public_key: Option<String>,
+
+    /// Write the private keys of all results as encrypted keystore v3 files into this
+    /// directory instead of printing them
+    #[clap(long, value_parser)]
+    pub keystore_dir: Option<String>,
+
+    /// Key derivation function for keystore files: "scrypt" or "pbkdf2"
+    #[clap(long, value_parser, default_value = "scrypt")]
+    pub keystore_kdf: String,
+
+    #[clap(flatten)]
+    pub password: PasswordArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct PasswordArgs {
+    /// Read the keystore password from this environment variable instead of prompting
+    #[clap(long, value_parser)]
+    pub password_env: Option<String>,
+
+    /// Read the keystore password from this file instead of prompting
+    #[clap(long, value_parser, conflicts_with = "password-env")]
+    pub password_file: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct HookArgs {
+    /// Uniswap v4 hook permissions for the hook strategy, e.g. "before-swap,after-swap" or "0x00c0"
+    #[clap(long, value_parser, required_if_eq("strategy", "hook"))]
+    pub hooks: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Search for an account address
+    Eoa {
+        #[clap(flatten)]
+        search: SearchArgs,
+
+        #[clap(flatten)]
+        key: KeyArgs,
+
+        /// Search BIP-39 mnemonics and derive the addresses at m/44'/60'/0'/0/i
+        #[clap(
+            long,
+            value_parser,
+            default_value_t = false,
+            conflicts_with = "public-key"
+        )]
+        mnemonic: bool,
+
+        /// Number of words of generated mnemonics: 12, 15, 18, 21 or 24
+        #[clap(long, value_parser, default_value_t = 12, requires = "mnemonic")]
+        mnemonic_words: usize,
+
+        /// Range of address indexes i to try for every mnemonic, e.g. "0..10" or "0..=9"
+        #[clap(long, value_parser, requires = "mnemonic")]
+        hd_index_range: Option<String>,
+    },
+    /// Search for the address of a contract deployed with CREATE by a new account
+    Contract {
+        #[clap(flatten)]
+        search: SearchArgs,
+
+        #[clap(flatten)]
+        key: KeyArgs,
+
+        #[clap(flatten)]
+        hooks: HookArgs,
+
+        /// Deployer nonce of the contract
+        #[clap(long, value_parser, conflicts_with = "nonce-range")]
+        nonce: Option<u64>,
+
+        /// Range of deployer nonces to try, e.g. "0..5" or "0..=4". With --continuous or
+        /// --min-score every matching nonce is reported, not only the best one of a key
+        #[clap(long, value_parser)]
+        nonce_range: Option<String>,
+
+        /// Pattern the deployer address has to match as well
+        #[clap(long, value_parser)]
+        eoa_pattern: Option<String>,
+
+        /// Strategy for --eoa-pattern, defaults to --strategy
+        #[clap(long, value_parser = ["contains", "startswith", "trailing"], requires = "eoa-pattern")]
+        eoa_strategy: Option<String>,
+    },
+    /// Search for a CREATE2 salt
+    Create2 {
+        #[clap(flatten)]
+        search: SearchArgs,
+
+        #[clap(flatten)]
+        hooks: HookArgs,
+
+        /// Address of the CREATE2 deployer
+        #[clap(long, value_parser)]
+        deployer: String,
+
+        /// Init code of the contract
+        #[clap(long, value_parser)]
+        bytecode: String,
+
+        /// Up to 24 hex bytes every salt starts with, e.g. the caller for factories that require it
+        #[clap(long, value_parser)]
+        salt_prefix: Option<String>,
+    },
+    /// Search for a CREATE3 salt, the address does not depend on the init code
+    Create3 {
+        #[clap(flatten)]
+        search: SearchArgs,
+
+        #[clap(flatten)]
+        hooks: HookArgs,
+
+        /// Address of the CREATE3 factory that deploys the proxy with CREATE2
+        #[clap(long, value_parser)]
+        deployer: String,
+
+        /// Up to 24 hex bytes every salt starts with, e.g. the caller for factories that require it
+        #[clap(long, value_parser)]
+        salt_prefix: Option<String>,
+    },
+    /// Show the expected number of attempts and search time for a pattern
+    Estimate {
+        #[clap(flatten)]
+        pattern: PatternArgs,
+
+        /// Search speed in addresses per second, measured for --mode when not given
+        #[clap(long, value_parser)]
+        speed: Option<u64>,
+
+        /// Search the speed is measured for
+        #[clap(long, value_parser = MODES, default_value = "eoa")]
+        mode: String,
+
+        /// Number of threads to measure the speed with, defaults to the available parallelism
+        #[clap(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+        threads: Option<u32>,
+    },
+    /// Decrypt a keystore file and verify that it matches its address
+    #[clap(alias = "verify-keystore")]
+    Verify {
+        /// Path of the keystore file
+        #[clap(value_parser)]
+        path: String,
+
+        /// Expected address of the key
+        #[clap(long, value_parser)]
+        address: Option<String>,
+
+        /// Print the decrypted private key
+        #[clap(long, value_parser, default_value_t = false)]
+        show_private_key: bool,
+
+        #[clap(flatten)]
+        password: PasswordArgs,
+    },
+    /// Add a split-key offset to your private key and verify the resulting address
+    Combine {
+        /// Offset found with --public-key
+        #[clap(long, value_parser)]
+        offset: String,
+
+        /// Expected address of the combined key
+        #[clap(long, value_parser)]
+        address: Option<String>,
+
+        /// Read the private key from this environment variable instead of prompting
+        #[clap(long, value_parser)]
+        private_key_env: Option<String>,
+
+        /// Read the private key from this file instead of prompting
+        #[clap(long, value_parser, conflicts_with = "private-key-env")]
+        private_key_file: Option<String>,
+    },
+    /// Measure how many addresses per second searches compute and where the time goes
+    Bench {
+        /// Searches to measure, separated by commas
+        #[clap(
+            long,
+            value_parser = MODES,
+            use_value_delimiter = true,
+            default_value = "eoa,contract,create2"
+        )]
+        mode: Vec<String>,
+
+        /// Highest number of threads, measured with 1, 2, 4, ... up to this many, defaults to
+        /// the available parallelism
+        #[clap(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+        threads: Option<u32>,
+
+        /// Duration of every measurement in seconds
+        #[clap(long, value_parser, default_value_t = 2)]
+        seconds: u64,
+
+        /// Output format of the report: "text" or "json"
+        #[clap(long, value_parser = ["text", "json"], default_value = "text")]
+        output_format: String,
+    },
+    /// List, filter and export the results stored by searches run with --store
+    Results {
+        /// Result store to read
+        #[clap(long, value_parser, default_value = "results.db")]
+        store: String,
+
+        /// Only results found with this pattern
+        #[clap(short, long, value_parser)]
+        pattern: Option<String>,
+
+        /// Only results with at least this score
+        #[clap(long, value_parser)]
+        min_score: Option<u64>,
+
+        /// Only results of this search
+        #[clap(long, value_parser = MODES)]
+        mode: Option<String>,
+
+        /// Number of results to show, best first
+        #[clap(long, value_parser)]
+        limit: Option<u64>,
+
+        /// "text" lists the results without their secrets, "json", "ndjson" and "csv" print
+        /// complete records
+        #[clap(long, value_parser, default_value = "text")]
+        output_format: String,
+
+        /// Write the complete records to this file instead of listing them
+        #[clap(long, value_parser)]
+        export: Option<String>,
+    },
+}
+
+pub(crate) fn parse_from(args: Vec<std::ffi::OsString>) -> Args {
+    Args::parse_from(args)
 }
 
-pub(crate) fn parse() -> Args {
-    Args::parse()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Args::command().debug_assert();
+        let args = Args::try_parse_from(["ranityeth", "eoa", "-s", "startswith", "-p", "dead"]);
+        assert!(matches!(args.unwrap().command, Command::Eoa { .. }));
+        // only contract searches take --hooks, which the hook strategy requires; hook on eoa
+        // parses and is rejected when the config is built
+        let hook = ["-s", "hook", "-p", "a"];
+        let with_hooks = |command: &str, hooks: &[&str]| {
+            Args::try_parse_from([&["ranityeth", command][..], &hook, hooks].concat())
+        };
+        assert!(with_hooks("eoa", &["--hooks", "0x80"]).is_err());
+        assert!(with_hooks("eoa", &[]).is_ok());
+        assert!(with_hooks("contract", &[]).is_err());
+        assert!(with_hooks("contract", &["--hooks", "0x80"]).is_ok());
+        // any positive thread count
+        let eoa = ["ranityeth", "eoa", "-s", "contains", "-p", "a", "-t"];
+        assert!(Args::try_parse_from([&eoa[..], &["1024"]].concat()).is_ok());
+        assert!(Args::try_parse_from([&eoa[..], &["0"]].concat()).is_err());
+        let results = Args::try_parse_from(["ranityeth", "results", "--min-score", "3"]);
+        assert!(matches!(results.unwrap().command, Command::Results { .. }));
+        assert!(Args::try_parse_from(["ranityeth", "results", "--mode", "nope"]).is_err());
+        assert!(
+            Args::try_parse_from(["ranityeth", "create2", "-s", "contains", "-p", "a"]).is_err()
+        );
+        assert!(Args::try_parse_from([
+            "ranityeth",
+            "contract",
+            "-s",
+            "contains",
+            "-p",
+            "a",
+            "--nonce",
+            "1",
+            "--nonce-range",
+            "0..2"
+        ])
+        .is_err());
+    }
 }
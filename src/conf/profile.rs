@@ -0,0 +1,351 @@
+// Config files and environment variables are turned into command line options before parsing,
+// so they go through exactly the same validation as flags. Options given on the command line
+// win over `RANITYETH_*` environment variables, which win over the selected profile, which
+// wins over the top level of the file.
+//
+//     threads = 8
+//
+//     [profiles.mainnet]
+//     command = "create2"
+//     deployer = "0x4e59b44847b379578588920ca78fbf26c0b4956c"
+//     bytecode = "0x6080..."
+//     strategy = "startswith"
+//     pattern = "dead"
+use super::parser::Args;
+use crate::error::{Error, Result};
+use clap::CommandFactory;
+use std::collections::HashSet;
+use std::ffi::OsString;
+
+const ENV_PREFIX: &str = "RANITYETH_";
+
+// Options that select the file itself or are handled by clap.
+const SKIPPED: [&str; 4] = ["config", "profile", "help", "version"];
+
+// The command line definition, built so that arguments report whether they take a value.
+fn command() -> clap::Command<'static> {
+    let mut command = Args::command();
+    command.build();
+    command
+}
+
+/// Returns `args` with the options of the config file and the environment appended, where
+/// `env` looks up environment variables.
+pub(crate) fn expand_args(
+    args: Vec<OsString>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<OsString>> {
+    let tokens: Vec<String> = args
+        .iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let config = option_value(&tokens, "config").or_else(|| env("RANITYETH_CONFIG"));
+    let profile = option_value(&tokens, "profile").or_else(|| env("RANITYETH_PROFILE"));
+
+    let settings = match &config {
+        Some(path) => load_settings(path, profile.as_deref())?,
+        None if profile.is_some() => {
+            return Err(Error::Config("--profile requires --config".to_string()))
+        }
+        None => toml::value::Table::new(),
+    };
+
+    let root = command();
+    let mut args = args;
+    let position = match subcommand_position(&tokens, &root) {
+        Some(position) => position,
+        None => match settings.get("command") {
+            // the profile names the search to run
+            Some(toml::Value::String(command)) => {
+                args.insert(1.min(args.len()), OsString::from(command));
+                1
+            }
+            Some(_) => return Err(Error::Config("'command' must be a string".to_string())),
+            // let clap report the missing subcommand
+            None => return Ok(args),
+        },
+    };
+    let name = args[position].to_string_lossy().into_owned();
+    let subcommand = match root.find_subcommand(name.as_str()) {
+        Some(subcommand) => subcommand,
+        None => return Ok(args),
+    };
+    let tokens: Vec<String> = args[position + 1..]
+        .iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let present = present_options(&tokens, subcommand);
+
+    for arg in subcommand.get_arguments() {
+        let long = match arg.get_long() {
+            Some(long) if !SKIPPED.contains(&long) && !present.contains(long) => long,
+            _ => continue,
+        };
+        let takes_value = arg.is_takes_value_set();
+        let env_name = format!("{}{}", ENV_PREFIX, long.to_uppercase().replace('-', "_"));
+
+        let value = match env(&env_name) {
+            Some(value) if takes_value => Some(value),
+            Some(value) => truthy(&value).then(String::new),
+            None => match settings.get(long) {
+                Some(value) => file_value(long, value, takes_value)?,
+                None => continue,
+            },
+        };
+        match value {
+            Some(value) if takes_value => args.push(format!("--{}={}", long, value).into()),
+            Some(_) => args.push(format!("--{}", long).into()),
+            None => {}
+        }
+    }
+    Ok(args)
+}
+
+// Reads the file and merges the profile over its top level, with keys normalized to the
+// option names.
+fn load_settings(path: &str, profile: Option<&str>) -> Result<toml::value::Table> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| Error::Config(format!("Unable to read config file {}: {}", path, e)))?;
+    let mut file: toml::value::Table = toml::from_str(&content)
+        .map_err(|e| Error::Config(format!("Invalid config file {}: {}", path, e)))?;
+
+    let profiles = match file.remove("profiles") {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => {
+            return Err(Error::Config(format!(
+                "'profiles' in {} must be a table",
+                path
+            )))
+        }
+        None => toml::value::Table::new(),
+    };
+    let mut settings = normalize(file, path)?;
+    if let Some(profile) = profile {
+        match profiles.get(profile) {
+            Some(toml::Value::Table(values)) => settings.extend(normalize(values.clone(), path)?),
+            _ => {
+                return Err(Error::Config(format!(
+                    "Profile '{}' not found in {}",
+                    profile, path
+                )))
+            }
+        }
+    }
+    Ok(settings)
+}
+
+fn normalize(table: toml::value::Table, path: &str) -> Result<toml::value::Table> {
+    let known = known_options();
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let key = key.replace('_', "-");
+            if key != "command" && !known.contains(&key) {
+                return Err(Error::Config(format!(
+                    "Unknown option '{}' in {}",
+                    key, path
+                )));
+            }
+            Ok((key, value))
+        })
+        .collect()
+}
+
+// Long option names of every subcommand.
+fn known_options() -> HashSet<String> {
+    command()
+        .get_subcommands()
+        .flat_map(|subcommand| subcommand.get_arguments())
+        .filter_map(|arg| arg.get_long())
+        .filter(|long| !SKIPPED.contains(long))
+        .map(str::to_string)
+        .collect()
+}
+
+fn file_value(long: &str, value: &toml::Value, takes_value: bool) -> Result<Option<String>> {
+    match (value, takes_value) {
+        (toml::Value::Boolean(flag), false) => Ok(flag.then(String::new)),
+        (toml::Value::String(value), true) => Ok(Some(value.clone())),
+        (toml::Value::Integer(value), true) => Ok(Some(value.to_string())),
+        (toml::Value::Boolean(value), true) => Ok(Some(value.to_string())),
+        _ => Err(Error::Config(format!("Invalid value for '{}'", long))),
+    }
+}
+
+fn truthy(value: &str) -> bool {
+    !matches!(
+        value.to_lowercase().as_str(),
+        "" | "0" | "false" | "no" | "off"
+    )
+}
+
+// Value of `--name value` or `--name=value`.
+fn option_value(tokens: &[String], name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+    let mut iter = tokens.iter();
+    while let Some(token) = iter.next() {
+        if *token == flag {
+            return iter.next().cloned();
+        }
+        if let Some(value) = token.strip_prefix(&format!("{}=", flag)) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+// Index of the subcommand in `tokens`, skipping the program name and global options.
+fn subcommand_position(tokens: &[String], root: &clap::Command) -> Option<usize> {
+    let mut index = 1;
+    while index < tokens.len() {
+        let token = &tokens[index];
+        if token == "--config" || token == "--profile" {
+            index += 2;
+            continue;
+        }
+        if !token.starts_with('-') {
+            return root.find_subcommand(token.as_str()).map(|_| index);
+        }
+        index += 1;
+    }
+    None
+}
+
+// Long names of the options given in `tokens`, as `--long`, `--long=value`, `-s`, `-svalue`
+// or a cluster of short flags like `-ct`.
+fn present_options(tokens: &[String], subcommand: &clap::Command) -> HashSet<String> {
+    let arguments: Vec<_> = subcommand.get_arguments().collect();
+    let by_short = |short: char| arguments.iter().find(|arg| arg.get_short() == Some(short));
+    let mut present = HashSet::new();
+    let mut skip_value = false;
+    for token in tokens {
+        if std::mem::take(&mut skip_value) {
+            continue;
+        }
+        if token == "--" {
+            break;
+        }
+        if let Some(long) = token.strip_prefix("--") {
+            let (long, attached) = match long.split_once('=') {
+                Some((long, _)) => (long, true),
+                None => (long, false),
+            };
+            if let Some(arg) = arguments.iter().find(|arg| arg.get_long() == Some(long)) {
+                skip_value = arg.is_takes_value_set() && !attached;
+            }
+            present.insert(long.to_string());
+        } else if let Some(shorts) = token.strip_prefix('-') {
+            for (i, short) in shorts.char_indices() {
+                let arg = match by_short(short) {
+                    Some(arg) => arg,
+                    None => break,
+                };
+                if let Some(long) = arg.get_long() {
+                    present.insert(long.to_string());
+                }
+                if arg.is_takes_value_set() {
+                    // the rest of the token is the value, or the next token is
+                    skip_value = i + short.len_utf8() == shorts.len();
+                    break;
+                }
+            }
+        }
+    }
+    present
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(args: &[&str], env: &[(&str, &str)]) -> Result<Vec<String>> {
+        let args = args.iter().map(OsString::from).collect();
+        let env = |name: &str| {
+            env.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        };
+        expand_args(args, env).map(|args| {
+            args.into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_profiles_and_overrides() {
+        let dir = crate::fs::tests::private_dir();
+        let path = dir.join("ranityeth.toml").display().to_string();
+        std::fs::write(
+            &path,
+            r#"
+threads = 8
+continuous = true
+
+[profiles.mainnet]
+command = "create2"
+deployer = "0x4e59b44847b379578588920ca78fbf26c0b4956c"
+strategy = "startswith"
+pattern = "dead"
+salt_prefix = "0x00"
+"#,
+        )
+        .unwrap();
+
+        let args = expand(
+            &["ranityeth", "--config", &path, "--profile", "mainnet"],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(args[1], "create2");
+        assert!(args.contains(&"--threads=8".to_string()));
+        assert!(args.contains(&"--continuous".to_string()));
+        assert!(args.contains(&"--pattern=dead".to_string()));
+        assert!(args.contains(&"--salt-prefix=0x00".to_string()));
+
+        // the command line wins over the environment, which wins over the file
+        let args = expand(
+            &["ranityeth", "create2", "-p", "beef", "--config", &path],
+            &[
+                ("RANITYETH_PROFILE", "mainnet"),
+                ("RANITYETH_THREADS", "2"),
+                ("RANITYETH_CONTINUOUS", "false"),
+                ("RANITYETH_PATTERN", "cafe"),
+            ],
+        )
+        .unwrap();
+        assert!(args.contains(&"--threads=2".to_string()));
+        assert!(!args.iter().any(|arg| arg.contains("pattern")));
+        assert!(!args.contains(&"--continuous".to_string()));
+
+        assert!(expand(
+            &["ranityeth", "--config", &path, "--profile", "testnet"],
+            &[]
+        )
+        .is_err());
+        std::fs::write(&path, "threds = 8\n").unwrap();
+        assert!(expand(&["ranityeth", "--config", &path, "eoa"], &[]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_present_options() {
+        let root = command();
+        let eoa = root.find_subcommand("eoa").unwrap();
+        let tokens: Vec<String> = ["-ct4", "-pdead", "--entropy=os", "--output-format", "-x"]
+            .iter()
+            .map(|token| token.to_string())
+            .collect();
+        let present = present_options(&tokens, eoa);
+        for long in [
+            "casesensitive",
+            "threads",
+            "pattern",
+            "entropy",
+            "output-format",
+        ] {
+            assert!(present.contains(long), "{}", long);
+        }
+        assert!(!present.contains("strategy"));
+    }
+}
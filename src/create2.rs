@@ -1,53 +1,216 @@
-use rand::RngCore;
+use crate::conf::config::AppConfig;
+use crate::entropy::KeyRng;
+use crate::error::Error;
+use crate::keccak;
 use tiny_keccak::{Hasher, Keccak};
 
-pub fn generate_salt() -> [u8; 32] {
+/// Init code of the proxy a CREATE3 factory deploys with CREATE2 (as in solmate and
+/// 0xSequence), the proxy then deploys the contract with CREATE at nonce 1.
+pub const CREATE3_PROXY_BYTECODE: &[u8] = &[
+    0x67, 0x36, 0x3d, 0x3d, 0x37, 0x36, 0x3d, 0x34, 0xf0, 0x3d, 0x52, 0x60, 0x08, 0x60, 0x18, 0xf3,
+];
+
+pub fn generate_salt(rng: &mut KeyRng) -> [u8; 32] {
     // Generate a random salt
     let mut salt = [0u8; 32];
-    rand::thread_rng().fill_bytes(&mut salt);
+    rng.fill_bytes(&mut salt);
     salt
 }
 
-pub fn derive_salt(salt: [u8; 32]) -> [u8; 32] {
-    // derive salt from the previous one
-    let mut derived_salt = salt;
-    let mut rng = rand::thread_rng();
-    let index = rng.next_u32() as usize % 32;
-    let value = rng.next_u32() as u8;
-    derived_salt[index] = value;
-    derived_salt.rotate_left(1);
-    derived_salt
+/// Checks that a salt prefix leaves the last 8 bytes of every salt to its counter.
+pub fn check_salt_prefix(prefix: &[u8]) -> Result<(), Error> {
+    match prefix.len() <= 24 {
+        true => Ok(()),
+        false => Err(Error::Config(
+            "Salt prefix must be at most 24 bytes, the last 8 bytes of the salt are a counter"
+                .to_string(),
+        )),
+    }
 }
 
-pub fn bytecode_keccak(bytecode: &str) -> [u8; 32] {
+/// Overwrites the first bytes of `salt` with `prefix`, e.g. a deployer address for factories
+/// that only accept salts starting with the caller.
+pub fn apply_salt_prefix(mut salt: [u8; 32], prefix: &[u8]) -> [u8; 32] {
+    salt[..prefix.len()].copy_from_slice(prefix);
+    salt
+}
+
+pub fn bytecode_keccak(bytecode: &[u8]) -> [u8; 32] {
     let mut keccak = Keccak::v256();
     let mut hash = [0u8; 32];
-    let _h = hex::decode(bytecode).unwrap();
-    keccak.update(&_h);
+    keccak.update(bytecode);
     keccak.finalize(&mut hash);
     hash
 }
 
-pub fn calc_addr(address: &str, salt: [u8; 32], bytecode_hash: [u8; 32]) -> String {
-    let address = hex::decode(address).unwrap();
-    // calculate address
-    let mut buf = [0; 85];
-    buf[0] = 0xFF;
-    buf[1..21].copy_from_slice(&address);
-    buf[21..53].copy_from_slice(&salt);
-    buf[53..85].copy_from_slice(&bytecode_hash);
+/// Init code hash the salted address is computed with: the contract's for CREATE2, the
+/// proxy's for CREATE3.
+pub fn init_code_hash(config: &AppConfig) -> [u8; 32] {
+    match config.create3 {
+        true => bytecode_keccak(CREATE3_PROXY_BYTECODE),
+        false => bytecode_keccak(&config.bytecode),
+    }
+}
+
+/// Address of the contract deployed by the CREATE3 proxy of `deployer` and `salt`, i.e.
+/// `keccak256(rlp([proxy, 1]))[12..]`.
+pub fn calc_create3_addr(deployer: &[u8; 20], salt: [u8; 32], proxy_hash: [u8; 32]) -> String {
+    let mut buf = [0; 23];
+    buf[0] = 0xd6;
+    buf[1] = 0x94;
+    buf[2..22].copy_from_slice(&create2_hash(deployer, salt, proxy_hash)[12..32]);
+    buf[22] = 0x01;
 
     let mut sha3 = Keccak::v256();
     sha3.update(&buf);
     let mut fin = [0; 32];
     sha3.finalize(&mut fin);
-    let mut calculated_addr = [0; 20];
-
-    calculated_addr.copy_from_slice(&fin[12..32]);
+    hex::encode(&fin[12..32])
+}
 
+pub fn calc_addr(deployer: &[u8; 20], salt: [u8; 32], bytecode_hash: [u8; 32]) -> String {
     // convert to hash
-    let hash = hex::encode(calculated_addr);
-    return hash;
+    hex::encode(&create2_hash(deployer, salt, bytecode_hash)[12..32])
+}
+
+fn create2_hash(deployer: &[u8; 20], salt: [u8; 32], bytecode_hash: [u8; 32]) -> [u8; 32] {
+    let buf = create2_preimage(deployer, salt, bytecode_hash);
+    let mut sha3 = Keccak::v256();
+    sha3.update(&buf);
+    let mut fin = [0; 32];
+    sha3.finalize(&mut fin);
+    fin
+}
+
+// `0xff ++ deployer ++ salt ++ init_code_hash`
+fn create2_preimage(deployer: &[u8; 20], salt: [u8; 32], bytecode_hash: [u8; 32]) -> [u8; 85] {
+    let mut buf = [0; 85];
+    buf[0] = 0xFF;
+    buf[1..21].copy_from_slice(deployer);
+    buf[21..53].copy_from_slice(&salt);
+    buf[53..85].copy_from_slice(&bytecode_hash);
+    buf
+}
+
+// The last 8 bytes of the salt count up, at bytes 45..53 of the preimage. They fall into
+// lanes 5 and 6 of the padded block (bytes 40..56), the other lanes never change.
+const COUNTER_LANE: usize = 5;
+const COUNTER_OFFSET: usize = 45 - COUNTER_LANE * 8;
+
+/// Precomputed CREATE2 (or CREATE3) hashing for one deployer and init code hash.
+///
+/// Salts are a base salt whose last 8 bytes are a big endian counter. The blocks are padded
+/// once, each batch only rewrites the two lanes holding the counter before all blocks are
+/// hashed together, and the addresses come back as raw bytes.
+pub struct Create2Context {
+    deployer: [u8; 20],
+    init_code_hash: [u8; 32],
+    create3: bool,
+    salt: [u8; 32],
+    // counter of the next salt
+    counter: u64,
+    blocks: Vec<keccak::Block>,
+    // `rlp([proxy, 1])` for CREATE3, the proxy is written into lanes 0 to 2
+    proxy_blocks: Vec<keccak::Block>,
+    hashes: Vec<[u8; 32]>,
+    addresses: Vec<[u8; 20]>,
+}
+
+impl Create2Context {
+    /// A context computing `batch` addresses at a time, starting at `salt`. `init_code_hash`
+    /// is the proxy's for CREATE3, see [`init_code_hash`].
+    pub fn new(
+        deployer: &[u8; 20],
+        init_code_hash: [u8; 32],
+        create3: bool,
+        salt: [u8; 32],
+        batch: usize,
+    ) -> Create2Context {
+        let mut context = Create2Context {
+            deployer: *deployer,
+            init_code_hash,
+            create3,
+            salt,
+            counter: 0,
+            blocks: vec![],
+            proxy_blocks: vec![],
+            hashes: vec![[0; 32]; batch],
+            addresses: vec![[0; 20]; batch],
+        };
+        context.set_salt(salt);
+        if create3 {
+            let mut rlp = [0; 23];
+            rlp[0] = 0xd6;
+            rlp[1] = 0x94;
+            rlp[22] = 0x01;
+            context.proxy_blocks = vec![keccak::pad(&rlp); batch];
+        }
+        context
+    }
+
+    /// Context of the deployer and init code of a search config, starting at `salt`.
+    pub fn from_config(config: &AppConfig, salt: [u8; 32], batch: usize) -> Create2Context {
+        Create2Context::new(
+            &config.deployer,
+            init_code_hash(config),
+            config.create3,
+            salt,
+            batch,
+        )
+    }
+
+    /// Continues from `salt`, the first 24 bytes are shared by all following salts.
+    pub fn set_salt(&mut self, salt: [u8; 32]) {
+        self.salt = salt;
+        self.counter = u64::from_be_bytes(salt[24..32].try_into().unwrap());
+        let block = keccak::pad(&create2_preimage(&self.deployer, salt, self.init_code_hash));
+        self.blocks = vec![block; self.addresses.len()];
+    }
+
+    /// Salt of the `i`th address of the last batch.
+    pub fn salt(&self, i: usize) -> [u8; 32] {
+        let first = self.counter.wrapping_sub(self.addresses.len() as u64);
+        let mut salt = self.salt;
+        salt[24..32].copy_from_slice(&first.wrapping_add(i as u64).to_be_bytes());
+        salt
+    }
+
+    /// Addresses of the last batch, see [`Create2Context::next_batch`].
+    pub fn addresses(&self) -> &[[u8; 20]] {
+        &self.addresses
+    }
+
+    /// Addresses of the next batch of salts, see [`Create2Context::salt`].
+    pub fn next_batch(&mut self) -> &[[u8; 20]] {
+        for block in self.blocks.iter_mut() {
+            let mut bytes = [0; 16];
+            bytes[..8].copy_from_slice(&block[COUNTER_LANE].to_le_bytes());
+            bytes[8..].copy_from_slice(&block[COUNTER_LANE + 1].to_le_bytes());
+            bytes[COUNTER_OFFSET..COUNTER_OFFSET + 8].copy_from_slice(&self.counter.to_be_bytes());
+            block[COUNTER_LANE] = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+            block[COUNTER_LANE + 1] = u64::from_le_bytes(bytes[8..].try_into().unwrap());
+            self.counter = self.counter.wrapping_add(1);
+        }
+        keccak::keccak256_blocks(&self.blocks, &mut self.hashes);
+        if self.create3 {
+            // the rlp prefix takes bytes 0..2, the proxy bytes 2..22 and the nonce byte 22
+            for (block, hash) in self.proxy_blocks.iter_mut().zip(&self.hashes) {
+                let mut bytes = [0; 24];
+                for (lane, chunk) in bytes.chunks_exact_mut(8).enumerate() {
+                    chunk.copy_from_slice(&block[lane].to_le_bytes());
+                }
+                bytes[2..22].copy_from_slice(&hash[12..32]);
+                for (lane, chunk) in bytes.chunks_exact(8).enumerate() {
+                    block[lane] = u64::from_le_bytes(chunk.try_into().unwrap());
+                }
+            }
+            keccak::keccak256_blocks(&self.proxy_blocks, &mut self.hashes);
+        }
+        for (address, hash) in self.addresses.iter_mut().zip(&self.hashes) {
+            address.copy_from_slice(&hash[12..32]);
+        }
+        &self.addresses
+    }
 }
 
 #[cfg(test)]
@@ -56,27 +219,72 @@ mod tests {
 
     #[test]
     fn test_calc_addr() {
-        let addr = "0000000000000000000000000000000000000000";
+        let addr = [0; 20];
         let mut salt_byte = [0; 32];
         let salt = "afe78640665423b7d1bc1ec9ad8f6c16b40a8330afd52489c99e150aeed11dc8";
         let _salt_byte = hex::decode(salt).unwrap();
         salt_byte.copy_from_slice(&_salt_byte);
-        let bytecode = "c0ffeec0ffeec0ffeec0ffeec0ffeec0ffeec0ffeec0ffeec0ffeec0ffeec0ffee";
-        let bytecode_hash = bytecode_keccak(bytecode);
-        let addr = calc_addr(addr, salt_byte, bytecode_hash);
+        let bytecode =
+            hex::decode("c0ffeec0ffeec0ffeec0ffeec0ffeec0ffeec0ffeec0ffeec0ffeec0ffeec0ffee")
+                .unwrap();
+        let bytecode_hash = bytecode_keccak(&bytecode);
+        let addr = calc_addr(&addr, salt_byte, bytecode_hash);
         assert_eq!(addr, "79624f7caed7018e447df443efda123c6ec123f1");
     }
 
     #[test]
-    fn test_generate_random_salt() {
-        let salt = generate_salt();
-        assert_eq!(salt.len(), 32);
+    fn test_calc_create3_addr() {
+        let deployer =
+            crate::eth::parse_address("4e59b44847b379578588920ca78fbf26c0b4956c").unwrap();
+        let salt = [7; 32];
+        let proxy_hash = bytecode_keccak(CREATE3_PROXY_BYTECODE);
+        assert_eq!(
+            hex::encode(proxy_hash),
+            "21c35dbe1b344a2488cf3321d6ce542f8e9f305544ff09e4993a62319a497c1f"
+        );
+        let proxy = crate::eth::Wallet {
+            private_key: crate::eth::PrivateKey::from_bytes([0; 32]),
+            public_key: calc_addr(&deployer, salt, proxy_hash),
+        };
+        assert_eq!(
+            calc_create3_addr(&deployer, salt, proxy_hash),
+            crate::eth::generate_contract_address_at(&proxy, 1)
+        );
+    }
+
+    #[test]
+    fn test_create2_context() {
+        let deployer =
+            crate::eth::parse_address("4e59b44847b379578588920ca78fbf26c0b4956c").unwrap();
+        let hash = bytecode_keccak(&[0xc0, 0xff, 0xee]);
+        let proxy_hash = bytecode_keccak(CREATE3_PROXY_BYTECODE);
+        // the counter wraps around without carrying into the salt bytes before it
+        let mut salt = [0xab; 32];
+        salt[24..32].copy_from_slice(&(u64::MAX - 5).to_be_bytes());
+        let mut create2 = Create2Context::new(&deployer, hash, false, salt, 8);
+        let mut create3 = Create2Context::new(&deployer, proxy_hash, true, salt, 7);
+        for _ in 0..3 {
+            let addresses = create2.next_batch().to_vec();
+            for (i, address) in addresses.iter().enumerate() {
+                let salt = create2.salt(i);
+                assert_eq!(salt[..24], [0xab; 24]);
+                assert_eq!(hex::encode(address), calc_addr(&deployer, salt, hash));
+            }
+            let addresses = create3.next_batch().to_vec();
+            for (i, address) in addresses.iter().enumerate() {
+                let salt = create3.salt(i);
+                assert_eq!(
+                    hex::encode(address),
+                    calc_create3_addr(&deployer, salt, proxy_hash)
+                );
+            }
+        }
+        assert_eq!(create2.salt(0)[24..32], 10u64.to_be_bytes());
     }
 
     #[test]
-    fn test_derive_salt() {
-        let salt = generate_salt();
-        let derived_salt = derive_salt(salt);
-        assert_ne!(salt, derived_salt);
+    fn test_generate_random_salt() {
+        let salt = generate_salt(&mut KeyRng::Os);
+        assert_eq!(salt.len(), 32);
     }
 }
@@ -0,0 +1,117 @@
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+// The ChaCha source pulls a fresh seed from the OS after this many output bytes.
+const RESEED_INTERVAL: u64 = 1 << 20;
+
+/// Where the randomness behind private keys and salts comes from.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EntropySource {
+    /// Every key is read directly from the operating system (`getrandom`).
+    Os,
+    /// ChaCha20 CSPRNG seeded and periodically reseeded from the operating system.
+    ChaCha,
+    /// Deterministic ChaCha20 stream from a fixed seed. Only meant for tests, never for keys
+    /// that are going to be used.
+    Seeded(u64),
+}
+
+impl EntropySource {
+    pub fn parse(source: &str) -> Option<EntropySource> {
+        match source {
+            "os" => Some(EntropySource::Os),
+            "chacha" => Some(EntropySource::ChaCha),
+            _ => source
+                .strip_prefix("seeded:")
+                .and_then(|seed| seed.parse().ok())
+                .map(EntropySource::Seeded),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntropySource::Os => "os",
+            EntropySource::ChaCha => "chacha20",
+            EntropySource::Seeded(_) => "seeded",
+        }
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        matches!(self, EntropySource::Seeded(_))
+    }
+
+    pub fn rng(&self) -> KeyRng {
+        match self {
+            EntropySource::Os => KeyRng::Os,
+            EntropySource::ChaCha => KeyRng::ChaCha {
+                rng: ChaCha20Rng::from_seed(os_seed()),
+                generated: 0,
+            },
+            EntropySource::Seeded(seed) => KeyRng::Seeded(ChaCha20Rng::seed_from_u64(*seed)),
+        }
+    }
+}
+
+/// Per-thread random generator for an [`EntropySource`].
+pub enum KeyRng {
+    Os,
+    ChaCha { rng: ChaCha20Rng, generated: u64 },
+    Seeded(ChaCha20Rng),
+}
+
+impl KeyRng {
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            KeyRng::Os => getrandom::getrandom(dest).expect("OS random number generator failed"),
+            KeyRng::ChaCha { rng, generated } => {
+                if *generated >= RESEED_INTERVAL {
+                    *rng = ChaCha20Rng::from_seed(os_seed());
+                    *generated = 0;
+                }
+                rng.fill_bytes(dest);
+                *generated += dest.len() as u64;
+            }
+            KeyRng::Seeded(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+}
+
+fn os_seed() -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).expect("OS random number generator failed");
+    seed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entropy_source() {
+        assert_eq!(EntropySource::parse("os"), Some(EntropySource::Os));
+        assert_eq!(EntropySource::parse("chacha"), Some(EntropySource::ChaCha));
+        assert_eq!(
+            EntropySource::parse("seeded:42"),
+            Some(EntropySource::Seeded(42))
+        );
+        assert_eq!(EntropySource::parse("seeded:"), None);
+        assert_eq!(EntropySource::parse("thread"), None);
+    }
+
+    #[test]
+    fn test_seeded_is_deterministic() {
+        let (mut a, mut b) = ([0u8; 32], [0u8; 32]);
+        EntropySource::Seeded(7).rng().fill_bytes(&mut a);
+        EntropySource::Seeded(7).rng().fill_bytes(&mut b);
+        assert_eq!(a, b);
+
+        EntropySource::ChaCha.rng().fill_bytes(&mut b);
+        assert_ne!(a, b);
+    }
+}
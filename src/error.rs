@@ -0,0 +1,62 @@
+use std::fmt;
+
+/// Errors returned from config building, input parsing and search setup. The CLI prints the
+/// message and exits with [`Error::exit_code`].
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid or contradicting options.
+    Config(String),
+    /// Malformed input such as hex strings, addresses, keys or offsets.
+    Input(String),
+    /// A keystore could not be read, decrypted or did not match.
+    Keystore(String),
+    /// A file could not be read or written.
+    Io(std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Process exit code: 2 for usage errors like clap's own, sysexits codes for bad input
+    /// data (65) and I/O failures (74), 1 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => 2,
+            Error::Input(_) => 65,
+            Error::Keystore(_) => 1,
+            Error::Io(_) => 74,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(message) | Error::Input(message) | Error::Keystore(message) => {
+                f.write_str(message)
+            }
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Io(std::io::Error::other(e))
+    }
+}
@@ -1,16 +1,61 @@
-use secp256k1::Secp256k1;
-use std::fmt::Write;
+use crate::entropy::KeyRng;
+use crate::error::Error;
+use crate::keccak;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use std::fmt::{self, Write};
 use tiny_keccak::Hasher;
 use tiny_keccak::Keccak;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+/// Raw private key bytes, wiped from memory on drop. It is only turned into hex at the
+/// output boundary with [`PrivateKey::to_hex`].
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct PrivateKey([u8; 32]);
+
+impl PrivateKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> PrivateKey {
+        PrivateKey(bytes)
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Option<PrivateKey> {
+        let bytes: [u8; 32] = bytes.try_into().ok()?;
+        Some(PrivateKey(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn to_hex(&self) -> Zeroizing<String> {
+        Zeroizing::new(hex::encode(self.0))
+    }
+}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PrivateKey(<redacted>)")
+    }
+}
+
+// secp256k1 keys are `Copy` and never wiped, so the copies we own are cleared explicitly.
+pub(crate) fn erase_secret_key(secret_key: &mut SecretKey) {
+    // SAFETY: the pointer covers exactly the `len()` bytes of the key we hold a unique
+    // reference to.
+    unsafe { std::slice::from_raw_parts_mut(secret_key.as_mut_ptr(), secret_key.len()) }.zeroize();
+}
 
 pub struct Wallet {
-    pub private_key: String,
+    pub private_key: PrivateKey,
     pub public_key: String,
 }
 
 impl Wallet {
     pub fn new() -> Wallet {
-        let (private_key, public_key) = generate_key_address();
+        Wallet::generate(&mut KeyRng::Os)
+    }
+
+    pub fn generate(rng: &mut KeyRng) -> Wallet {
+        let (private_key, public_key) = generate_key_address(rng);
         Wallet {
             private_key,
             public_key,
@@ -25,15 +70,18 @@ impl Default for Wallet {
 }
 
 pub fn generate_contract_address(wallet: &Wallet) -> String {
-    let checksummed_address = checksum(&wallet.public_key);
+    generate_contract_address_at(wallet, 0)
+}
 
-    let bytes = hex::decode(checksummed_address).expect("Unable to unwrap address");
-    let nonce: Vec<u8> = vec![0];
+/// Address of the contract created by `wallet` when its account nonce is `nonce`,
+/// i.e. `keccak256(rlp([sender, nonce]))[12..]`.
+pub fn generate_contract_address_at(wallet: &Wallet, nonce: u64) -> String {
+    let bytes = hex::decode(&wallet.public_key).expect("Unable to unwrap address");
 
-    let arr = [&bytes, &nonce];
-    let mut encoded = rlp::encode_list::<Vec<u8>, _>(&arr);
-    encoded.truncate(encoded.len() - 1);
-    encoded.extend(&[128u8]);
+    let mut stream = rlp::RlpStream::new_list(2);
+    stream.append(&bytes);
+    stream.append(&nonce);
+    let encoded = stream.out();
 
     let mut sha3 = Keccak::v256();
 
@@ -50,18 +98,90 @@ pub fn generate_contract_address(wallet: &Wallet) -> String {
     address_string
 }
 
-pub fn generate_key_address() -> (String, String) {
-    let mut rng = rand::thread_rng();
-    let context = Secp256k1::new();
-
-    let (private_key, public_key) = context.generate_keypair(&mut rng);
+/// Addresses of the contracts created by each of `wallets` at `nonce`, hashed together.
+pub fn generate_contract_addresses_at(wallets: &[Wallet], nonce: u64) -> Vec<String> {
+    let encoded: Vec<_> = wallets
+        .iter()
+        .map(|wallet| {
+            let bytes = hex::decode(&wallet.public_key).expect("Unable to unwrap address");
+            let mut stream = rlp::RlpStream::new_list(2);
+            stream.append(&bytes);
+            stream.append(&nonce);
+            stream.out()
+        })
+        .collect();
+    let messages: Vec<&[u8]> = encoded.iter().map(|encoded| &encoded[..]).collect();
+    keccak::keccak256_batch(&messages)
+        .iter()
+        .map(|hash| hex::encode(&hash[12..]))
+        .collect()
+}
 
-    let mut private_key_string = String::new();
+/// Generates `count` wallets, hashing their public keys together.
+pub fn generate_wallets(rng: &mut KeyRng, count: usize) -> Vec<Wallet> {
+    let context = Secp256k1::signing_only();
+    let mut private_keys = Vec::with_capacity(count);
+    let mut public_keys = Vec::with_capacity(count);
+    while private_keys.len() < count {
+        let mut bytes = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(&mut bytes[..]);
+        // as in generate_key_address
+        if let Ok(mut secret_key) = SecretKey::from_slice(&bytes[..]) {
+            public_keys.push(PublicKey::from_secret_key(&context, &secret_key));
+            erase_secret_key(&mut secret_key);
+            private_keys.push(PrivateKey::from_bytes(*bytes));
+        }
+    }
+    private_keys
+        .into_iter()
+        .zip(public_key_addresses(&public_keys))
+        .map(|(private_key, public_key)| Wallet {
+            private_key,
+            public_key,
+        })
+        .collect()
+}
 
-    for &byte in private_key[..].iter() {
-        write!(&mut private_key_string, "{:02x}", byte).expect("Unable to write");
+pub fn generate_key_address(rng: &mut KeyRng) -> (PrivateKey, String) {
+    let context = Secp256k1::signing_only();
+    loop {
+        let mut bytes = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(&mut bytes[..]);
+        // values outside of the curve order are rejected, which happens with probability ~2^-128
+        if let Ok(mut secret_key) = SecretKey::from_slice(&bytes[..]) {
+            let public_key = PublicKey::from_secret_key(&context, &secret_key);
+            erase_secret_key(&mut secret_key);
+            return (
+                PrivateKey::from_bytes(*bytes),
+                public_key_address(&public_key),
+            );
+        }
     }
+}
 
+/// Derives the (lowercase, unprefixed) address of a raw private key.
+pub fn private_key_address(private_key: &[u8]) -> Result<String, secp256k1::Error> {
+    let context = Secp256k1::signing_only();
+    let mut secret_key = SecretKey::from_slice(private_key)?;
+    let public_key = PublicKey::from_secret_key(&context, &secret_key);
+    erase_secret_key(&mut secret_key);
+    Ok(public_key_address(&public_key))
+}
+
+/// Addresses of many public keys, hashed together.
+pub fn public_key_addresses(public_keys: &[PublicKey]) -> Vec<String> {
+    let serialized: Vec<_> = public_keys
+        .iter()
+        .map(PublicKey::serialize_uncompressed)
+        .collect();
+    let messages: Vec<&[u8]> = serialized.iter().map(|key| &key[1..65]).collect();
+    keccak::keccak256_batch(&messages)
+        .iter()
+        .map(|hash| hex::encode(&hash[12..]))
+        .collect()
+}
+
+pub fn public_key_address(public_key: &PublicKey) -> String {
     let mut sha3 = Keccak::v256();
     sha3.update(&public_key.serialize_uncompressed()[1..65]);
 
@@ -73,7 +193,14 @@ pub fn generate_key_address() -> (String, String) {
         write!(&mut address_string, "{:02x}", byte).expect("Unable to write");
     }
 
-    (private_key_string, address_string)
+    address_string
+}
+
+/// Parses a hex address with or without `0x` prefix.
+pub fn parse_address(address: &str) -> Result<[u8; 20], Error> {
+    let invalid = || Error::Input(format!("Invalid address: {}", address));
+    let bytes = hex::decode(address.trim_start_matches("0x")).map_err(|_| invalid())?;
+    bytes.try_into().map_err(|_| invalid())
 }
 
 pub fn checksum(address: &str) -> String {
@@ -1,44 +1,158 @@
-use std::io::Write;
+// Files holding private keys: created for the current user only and checked for other users'
+// access before anything is written.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-pub fn append_to_file(path: &str, content: &str) -> Result<(), std::io::Error> {
-    // check if file exists
-    let mut file = match std::fs::OpenOptions::new()
+/// Refuses to write secrets to `path` when other users could read or tamper with it: the file
+/// exists with group or other permissions, is a symlink, or its directory is writable by
+/// everyone.
+#[cfg(unix)]
+pub fn check_private_path(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let refuse = |message: String| Err(io::Error::new(io::ErrorKind::PermissionDenied, message));
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if fs::metadata(dir)?.permissions().mode() & 0o002 != 0 {
+        return refuse(format!(
+            "{} is writable by every user, refusing to write keys there",
+            dir.display()
+        ));
+    }
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => refuse(format!(
+            "{} is a symlink, refusing to write keys to it",
+            path.display()
+        )),
+        Ok(metadata) if metadata.permissions().mode() & 0o077 != 0 => refuse(format!(
+            "{} is accessible by other users, refusing to write keys to it (chmod 600 {})",
+            path.display(),
+            path.display()
+        )),
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn check_private_path(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn private_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600).custom_flags(libc::O_NOFOLLOW);
+    }
+    options
+}
+
+/// Opens `path` for appending, creating it readable and writable by the current user only.
+pub fn open_private(path: &Path) -> io::Result<File> {
+    private_options().append(true).create(true).open(path)
+}
+
+/// Opens `path` for reading and writing at any position, creating it readable and writable
+/// by the current user only.
+pub fn open_private_rw(path: &Path) -> io::Result<File> {
+    private_options()
+        .read(true)
         .write(true)
-        .append(true)
         .create(true)
         .open(path)
-    {
-        Ok(file) => file,
-        Err(e) => return Err(e),
-    };
+}
 
-    file.write_all(content.as_bytes())?;
-    Ok(())
+/// Creates the directory `path` and its missing parents, accessible by the current user only.
+pub fn create_private_dir(path: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(path)
+}
+
+/// Replaces the content of `path` as a whole: written to a temporary file next to it, synced
+/// and renamed over it, so readers never see a partial file.
+pub fn replace_private(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = private_options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// Moves `path` out of the way to the first free `path.1`, `path.2`, ... and returns where it
+/// went. Older files are never overwritten.
+pub fn rotate(path: &Path) -> io::Result<PathBuf> {
+    for n in 1.. {
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(format!(".{}", n));
+        let rotated = PathBuf::from(rotated);
+        if fs::symlink_metadata(&rotated).is_err() {
+            fs::rename(path, &rotated)?;
+            return Ok(rotated);
+        }
+    }
+    unreachable!()
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
-    use std::fs;
-    use std::io::prelude::*;
+
+    /// A new directory only the current user can access, as temp_dir is usually writable by
+    /// everyone.
+    pub(crate) fn private_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ranityeth-{}", rand::random::<u64>()));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&dir).unwrap();
+        dir
+    }
 
     #[test]
-    fn test_append_to_file() {
-        // generate a random path
-        let path = format!("/tmp/{}", rand::random::<u64>());
-        let path = path.as_str();
-
-        let content = "test";
-        append_to_file(path, content).unwrap();
-        let mut file = fs::File::open(path).unwrap();
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).unwrap();
-        assert_eq!(contents, content);
-
-        append_to_file(path, content).unwrap();
-        let mut file = fs::File::open(path).unwrap();
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).unwrap();
-        assert_eq!(contents, format!("{}{}", content, content));
+    fn test_open_private() {
+        let dir = private_dir();
+        let path = dir.join("keys.txt");
+        check_private_path(&path).unwrap();
+        open_private(&path).unwrap().write_all(b"test").unwrap();
+        open_private(&path).unwrap().write_all(b"test").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "testtest");
+        check_private_path(&path).unwrap();
+
+        assert_eq!(rotate(&path).unwrap(), dir.join("keys.txt.1"));
+        replace_private(&path, b"new").unwrap();
+        assert_eq!(rotate(&path).unwrap(), dir.join("keys.txt.2"));
+        assert_eq!(fs::read_to_string(dir.join("keys.txt.2")).unwrap(), "new");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let rotated = dir.join("keys.txt.1");
+            assert_eq!(
+                fs::metadata(&rotated).unwrap().permissions().mode() & 0o777,
+                0o600
+            );
+            fs::set_permissions(&rotated, fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(check_private_path(&rotated).is_err());
+            let tmp = std::env::temp_dir();
+            if fs::metadata(&tmp).unwrap().permissions().mode() & 0o002 != 0 {
+                assert!(check_private_path(&tmp.join("keys.txt")).is_err());
+            }
+        }
+        fs::remove_dir_all(dir).unwrap();
     }
 }
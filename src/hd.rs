@@ -0,0 +1,246 @@
+// BIP-39 mnemonic search: every candidate is a fresh seed phrase whose keys are derived along
+// the standard Ethereum path `m/44'/60'/0'/0/i` (BIP-32), so hits can be restored in any
+// hardware or software wallet.
+use crate::entropy::KeyRng;
+use crate::eth::{self, PrivateKey, Wallet};
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use secp256k1::{All, PublicKey, Secp256k1, SecretKey};
+use sha2::Sha512;
+use std::ops::Range;
+use zeroize::Zeroizing;
+
+/// Parent of the derived addresses, the address index is appended to it.
+pub const ACCOUNT_PATH: &str = "m/44'/60'/0'/0";
+
+const HARDENED: u32 = 1 << 31;
+
+type HmacSha512 = Hmac<Sha512>;
+
+fn finalize(mac: HmacSha512) -> Zeroizing<[u8; 64]> {
+    let mut output = Zeroizing::new([0u8; 64]);
+    output.copy_from_slice(&mac.finalize().into_bytes());
+    output
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MnemonicConfig {
+    /// Number of words of generated phrases: 12, 15, 18, 21 or 24.
+    pub words: usize,
+    /// Address indexes tried for every phrase.
+    pub indexes: Range<u32>,
+}
+
+/// The phrase and derivation path of a found key.
+pub struct MnemonicKey {
+    pub phrase: Zeroizing<String>,
+    pub path: String,
+}
+
+// A BIP-32 extended private key, with its public key computed once for non-hardened children
+// and the address.
+struct ExtendedKey {
+    secret_key: SecretKey,
+    public_key: PublicKey,
+    chain_code: Zeroizing<[u8; 32]>,
+}
+
+impl Drop for ExtendedKey {
+    fn drop(&mut self) {
+        eth::erase_secret_key(&mut self.secret_key);
+    }
+}
+
+impl ExtendedKey {
+    fn master(context: &Secp256k1<All>, seed: &[u8]) -> Option<ExtendedKey> {
+        let mut mac = HmacSha512::new_from_slice(b"Bitcoin seed").expect("HMAC takes any key");
+        mac.update(seed);
+        let output = finalize(mac);
+        let mut secret_key = SecretKey::from_slice(&output[..32]).ok()?;
+        let key = ExtendedKey::new(context, secret_key, &output[32..]);
+        eth::erase_secret_key(&mut secret_key);
+        Some(key)
+    }
+
+    fn new(context: &Secp256k1<All>, secret_key: SecretKey, chain_code: &[u8]) -> ExtendedKey {
+        let mut key = ExtendedKey {
+            secret_key,
+            public_key: PublicKey::from_secret_key(context, &secret_key),
+            chain_code: Zeroizing::new([0u8; 32]),
+        };
+        key.chain_code.copy_from_slice(chain_code);
+        key
+    }
+
+    /// Private child derivation. Returns `None` for the (~2^-127 likely) invalid children,
+    /// which BIP-32 says to skip.
+    fn child(&self, context: &Secp256k1<All>, index: u32) -> Option<ExtendedKey> {
+        let mut mac = HmacSha512::new_from_slice(&self.chain_code[..]).expect("HMAC takes any key");
+        if index >= HARDENED {
+            mac.update(&[0]);
+            mac.update(&self.secret_key[..]);
+        } else {
+            mac.update(&self.public_key.serialize());
+        }
+        mac.update(&index.to_be_bytes());
+        let output = finalize(mac);
+
+        // the child key is parse256(IL) + k_par, which fails if IL >= n or the sum is zero
+        let mut secret_key = SecretKey::from_slice(&output[..32]).ok()?;
+        let valid = secret_key.add_assign(&self.secret_key[..]).is_ok();
+        let child = valid.then(|| ExtendedKey::new(context, secret_key, &output[32..]));
+        eth::erase_secret_key(&mut secret_key);
+        child
+    }
+}
+
+/// Parses a derivation path such as `m/44'/60'/0'/0/3`.
+pub fn parse_path(path: &str) -> Result<Vec<u32>, String> {
+    let invalid = || format!("Invalid derivation path: {}", path);
+    let mut parts = path.split('/');
+    if parts.next() != Some("m") {
+        return Err(invalid());
+    }
+    parts
+        .map(|part| {
+            let (index, hardened) = match part.strip_suffix('\'') {
+                Some(index) => (index, true),
+                None => (part, false),
+            };
+            let index: u32 = index.parse().map_err(|_| invalid())?;
+            if index >= HARDENED {
+                return Err(invalid());
+            }
+            Ok(if hardened { index + HARDENED } else { index })
+        })
+        .collect()
+}
+
+/// Derives the private key at `path` from a mnemonic phrase without passphrase.
+pub fn derive_private_key(phrase: &str, path: &str) -> Result<PrivateKey, String> {
+    let mnemonic =
+        Mnemonic::parse_normalized(phrase).map_err(|e| format!("Invalid mnemonic: {}", e))?;
+    let seed = Zeroizing::new(mnemonic.to_seed_normalized(""));
+    let context = Secp256k1::new();
+    let mut key = ExtendedKey::master(&context, &seed[..]).ok_or("Invalid master key")?;
+    for index in parse_path(path)? {
+        key = key
+            .child(&context, index)
+            .ok_or_else(|| format!("Invalid child key at {}", path))?;
+    }
+    Ok(PrivateKey::from_slice(&key.secret_key[..]).expect("Keys are 32 bytes"))
+}
+
+/// Generates phrases and derives the wallets at the configured indexes.
+pub struct MnemonicSearch {
+    context: Secp256k1<All>,
+    config: MnemonicConfig,
+    phrase: Zeroizing<String>,
+    account: Option<ExtendedKey>,
+}
+
+impl MnemonicSearch {
+    pub fn new(config: &MnemonicConfig) -> MnemonicSearch {
+        MnemonicSearch {
+            context: Secp256k1::new(),
+            config: config.clone(),
+            phrase: Zeroizing::new(String::new()),
+            account: None,
+        }
+    }
+
+    /// Generates a fresh phrase and derives its account key `m/44'/60'/0'/0`.
+    pub fn next_mnemonic(&mut self, rng: &mut KeyRng) {
+        loop {
+            // 4 bytes of entropy per 3 words
+            let mut buffer = Zeroizing::new([0u8; 32]);
+            let entropy = &mut buffer[..self.config.words / 3 * 4];
+            rng.fill_bytes(entropy);
+            let mnemonic = Mnemonic::from_entropy(entropy).expect("Entropy has a valid length");
+            let seed = Zeroizing::new(mnemonic.to_seed_normalized(""));
+
+            let account = ExtendedKey::master(&self.context, &seed[..]).and_then(|master| {
+                parse_path(ACCOUNT_PATH)
+                    .expect("Account path is valid")
+                    .into_iter()
+                    .try_fold(master, |key, index| key.child(&self.context, index))
+            });
+            if let Some(account) = account {
+                self.phrase = Zeroizing::new(mnemonic.to_string());
+                self.account = Some(account);
+                return;
+            }
+        }
+    }
+
+    pub fn indexes(&self) -> Range<u32> {
+        self.config.indexes.clone()
+    }
+
+    /// Wallet at address index `index` of the current phrase, `None` if that child is invalid.
+    pub fn wallet_at(&self, index: u32) -> Option<Wallet> {
+        let account = self.account.as_ref().expect("No mnemonic generated");
+        let child = account.child(&self.context, index)?;
+        Some(Wallet {
+            private_key: PrivateKey::from_slice(&child.secret_key[..]).expect("Keys are 32 bytes"),
+            public_key: eth::public_key_address(&child.public_key),
+        })
+    }
+
+    pub fn key_at(&self, index: u32) -> MnemonicKey {
+        MnemonicKey {
+            phrase: self.phrase.clone(),
+            path: format!("{}/{}", ACCOUNT_PATH, index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHRASE: &str = "test test test test test test test test test test test junk";
+
+    #[test]
+    fn test_derive_private_key() {
+        let key = derive_private_key(PHRASE, "m/44'/60'/0'/0/0").unwrap();
+        assert_eq!(
+            *key.to_hex(),
+            "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+        );
+        let key = derive_private_key(PHRASE, "m/44'/60'/0'/0/1").unwrap();
+        assert_eq!(
+            eth::private_key_address(key.as_bytes()).unwrap(),
+            "70997970c51812dc3a010c7d01b50e0d17dc79c8"
+        );
+        assert!(derive_private_key("test test test", "m/0").is_err());
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("m/44'/60'/0'/0/7").unwrap(),
+            vec![44 + HARDENED, 60 + HARDENED, HARDENED, 0, 7]
+        );
+        assert!(parse_path("44'/60'").is_err());
+        assert!(parse_path("m/2147483648").is_err());
+    }
+
+    #[test]
+    fn test_search_matches_derivation() {
+        let mut search = MnemonicSearch::new(&MnemonicConfig {
+            words: 24,
+            indexes: 0..2,
+        });
+        search.next_mnemonic(&mut KeyRng::Os);
+        let wallet = search.wallet_at(1).unwrap();
+        let key = search.key_at(1);
+        assert_eq!(key.phrase.split(' ').count(), 24);
+        assert_eq!(
+            derive_private_key(&key.phrase, &key.path)
+                .unwrap()
+                .as_bytes(),
+            wallet.private_key.as_bytes()
+        );
+    }
+}
@@ -0,0 +1,99 @@
+// Uniswap v4 hook permissions are encoded in the lowest 14 bits of the hook address.
+// See `Hooks.sol` in the v4-core repository for the canonical list.
+pub const ALL_HOOK_MASK: u16 = (1 << 14) - 1;
+
+pub const HOOK_FLAGS: [(&str, u16); 14] = [
+    ("before-initialize", 1 << 13),
+    ("after-initialize", 1 << 12),
+    ("before-add-liquidity", 1 << 11),
+    ("after-add-liquidity", 1 << 10),
+    ("before-remove-liquidity", 1 << 9),
+    ("after-remove-liquidity", 1 << 8),
+    ("before-swap", 1 << 7),
+    ("after-swap", 1 << 6),
+    ("before-donate", 1 << 5),
+    ("after-donate", 1 << 4),
+    ("before-swap-returns-delta", 1 << 3),
+    ("after-swap-returns-delta", 1 << 2),
+    ("after-add-liquidity-returns-delta", 1 << 1),
+    ("after-remove-liquidity-returns-delta", 1),
+];
+
+/// Parses either a raw bitmask (`0x00c0`, `192`) or a comma separated list of
+/// permission names (`before-swap,after-swap`) into a hook flag bitmask.
+pub fn parse_hook_flags(input: &str) -> Result<u16, String> {
+    let input = input.trim();
+
+    let raw = if let Some(hex) = input.strip_prefix("0x") {
+        Some(u16::from_str_radix(hex, 16).map_err(|e| e.to_string())?)
+    } else if !input.is_empty() && input.bytes().all(|c| c.is_ascii_digit()) {
+        Some(input.parse::<u16>().map_err(|e| e.to_string())?)
+    } else {
+        None
+    };
+
+    if let Some(flags) = raw {
+        if flags & !ALL_HOOK_MASK != 0 {
+            return Err(format!(
+                "Hook bitmask {:#06x} uses more than 14 bits",
+                flags
+            ));
+        }
+        return Ok(flags);
+    }
+
+    let mut flags = 0;
+    for name in input.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let normalized = name.to_lowercase().replace('_', "-");
+        match HOOK_FLAGS.iter().find(|(n, _)| *n == normalized) {
+            Some((_, flag)) => flags |= flag,
+            None => return Err(format!("Unknown hook permission: {}", name)),
+        }
+    }
+    Ok(flags)
+}
+
+/// Returns the hook flags encoded in a hex address (with or without checksum casing), `None`
+/// if it does not end in four hex digits.
+pub fn address_flags(address: &str) -> Option<u16> {
+    let tail = address.get(address.len().checked_sub(4)?..)?;
+    let flags = u16::from_str_radix(tail, 16).ok()?;
+    Some(flags & ALL_HOOK_MASK)
+}
+
+pub fn flag_names(flags: u16) -> Vec<&'static str> {
+    HOOK_FLAGS
+        .iter()
+        .filter(|(_, flag)| flags & flag != 0)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hook_flags() {
+        assert_eq!(parse_hook_flags("before-swap,after-swap").unwrap(), 0xc0);
+        assert_eq!(parse_hook_flags("BEFORE_SWAP").unwrap(), 0x80);
+        assert_eq!(parse_hook_flags("0x00c0").unwrap(), 0xc0);
+        assert_eq!(parse_hook_flags("192").unwrap(), 0xc0);
+        assert!(parse_hook_flags("0x4000").is_err());
+        assert!(parse_hook_flags("before-lunch").is_err());
+    }
+
+    #[test]
+    fn test_address_flags() {
+        let address = "00000000000000000000000000000000000040c0";
+        assert_eq!(address_flags(address), Some(0xc0));
+        assert_eq!(
+            address_flags("000000000000000000000000000000000000FFFF"),
+            Some(ALL_HOOK_MASK)
+        );
+        assert_eq!(address_flags("c0"), None);
+        assert_eq!(address_flags("0x00zz"), None);
+        assert_eq!(address_flags("0x0\u{e9}c0"), None);
+        assert_eq!(flag_names(0xc0), vec!["before-swap", "after-swap"]);
+    }
+}
@@ -0,0 +1,383 @@
+// Multi-lane Keccak-256 for hashing many short candidates at once. Every message has to fit
+// into a single 136 byte block, which covers public keys (64 bytes), CREATE2 preimages
+// (85 bytes) and RLP encoded contract creations. The permutation runs on 8 messages with
+// AVX-512, 4 with AVX2 and one at a time otherwise, picked at runtime.
+use std::sync::OnceLock;
+
+/// Longest message that fits into one block with its padding.
+pub const MAX_LEN: usize = RATE - 1;
+
+/// Rate of Keccak-256 in bytes and in 64-bit lanes.
+pub const RATE: usize = 136;
+pub const RATE_LANES: usize = RATE / 8;
+
+/// A padded single block message as little endian lanes, see [`pad`].
+pub type Block = [u64; RATE_LANES];
+
+const RC: [u64; 24] = [
+    0x0000000000000001,
+    0x0000000000008082,
+    0x800000000000808a,
+    0x8000000080008000,
+    0x000000000000808b,
+    0x0000000080000001,
+    0x8000000080008081,
+    0x8000000000008009,
+    0x000000000000008a,
+    0x0000000000000088,
+    0x0000000080008009,
+    0x000000008000000a,
+    0x000000008000808b,
+    0x800000000000008b,
+    0x8000000000008089,
+    0x8000000000008003,
+    0x8000000000008002,
+    0x8000000000000080,
+    0x000000000000800a,
+    0x800000008000000a,
+    0x8000000080008081,
+    0x8000000000008080,
+    0x0000000080000001,
+    0x8000000080008008,
+];
+const RHO: [u32; 24] = [
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+];
+const PI: [usize; 24] = [
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+];
+
+// Keccak-f[1600] over a state of 25 lanes, with the lane operations given as macros so that
+// every backend expands it inside its own `target_feature` function.
+macro_rules! keccak_f {
+    ($a:ident, $xor:ident, $andnot:ident, $rotl:ident, $splat:ident) => {
+        for rc in RC {
+            // theta
+            let mut c = [$a[0]; 5];
+            for x in 0..5 {
+                c[x] = $xor!(
+                    $xor!($xor!($a[x], $a[x + 5]), $xor!($a[x + 10], $a[x + 15])),
+                    $a[x + 20]
+                );
+            }
+            for x in 0..5 {
+                let d = $xor!(c[(x + 4) % 5], $rotl!(c[(x + 1) % 5], 1));
+                for y in 0..5 {
+                    $a[y * 5 + x] = $xor!($a[y * 5 + x], d);
+                }
+            }
+            // rho and pi
+            let mut last = $a[1];
+            for i in 0..24 {
+                let next = $a[PI[i]];
+                $a[PI[i]] = $rotl!(last, RHO[i]);
+                last = next;
+            }
+            // chi
+            for y in 0..5 {
+                let row = [
+                    $a[y * 5],
+                    $a[y * 5 + 1],
+                    $a[y * 5 + 2],
+                    $a[y * 5 + 3],
+                    $a[y * 5 + 4],
+                ];
+                for x in 0..5 {
+                    $a[y * 5 + x] = $xor!(row[x], $andnot!(row[(x + 1) % 5], row[(x + 2) % 5]));
+                }
+            }
+            // iota
+            $a[0] = $xor!($a[0], $splat!(rc));
+        }
+    };
+}
+
+/// Implementation the permutation runs on.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Backend {
+    Portable,
+    Avx2,
+    Avx512,
+}
+
+impl Backend {
+    /// Widest implementation the CPU supports.
+    pub fn detect() -> Backend {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx512f") {
+                return Backend::Avx512;
+            }
+            if is_x86_feature_detected!("avx2") {
+                return Backend::Avx2;
+            }
+        }
+        Backend::Portable
+    }
+
+    /// Number of messages hashed at once.
+    pub fn lanes(&self) -> usize {
+        match self {
+            Backend::Portable => 1,
+            Backend::Avx2 => 4,
+            Backend::Avx512 => 8,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Portable => "portable",
+            Backend::Avx2 => "avx2",
+            Backend::Avx512 => "avx512",
+        }
+    }
+
+    // Hashes `blocks.len()` padded blocks, which has to be a multiple of `lanes()`.
+    fn hash_blocks(&self, blocks: &[Block], out: &mut [[u8; 32]]) {
+        match self {
+            Backend::Portable => {
+                for (block, out) in blocks.iter().zip(out) {
+                    *out = portable(block);
+                }
+            }
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2 => {
+                for (blocks, out) in blocks.chunks_exact(4).zip(out.chunks_exact_mut(4)) {
+                    // SAFETY: this backend is only selected when the CPU supports AVX2.
+                    unsafe { x86::avx2(blocks.try_into().unwrap(), out.try_into().unwrap()) };
+                }
+            }
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx512 => {
+                for (blocks, out) in blocks.chunks_exact(8).zip(out.chunks_exact_mut(8)) {
+                    // SAFETY: this backend is only selected when the CPU supports AVX-512F.
+                    unsafe { x86::avx512(blocks.try_into().unwrap(), out.try_into().unwrap()) };
+                }
+            }
+            #[cfg(not(target_arch = "x86_64"))]
+            _ => unreachable!("SIMD backends are only detected on x86_64"),
+        }
+    }
+}
+
+/// Backend used by [`keccak256_batch`], detected on first use.
+pub fn backend() -> Backend {
+    static BACKEND: OnceLock<Backend> = OnceLock::new();
+    *BACKEND.get_or_init(Backend::detect)
+}
+
+/// Keccak-256 of every message, each at most [`MAX_LEN`] bytes long.
+pub fn keccak256_batch(messages: &[&[u8]]) -> Vec<[u8; 32]> {
+    keccak256_batch_with(backend(), messages)
+}
+
+// Like `keccak256_batch` on a given backend, which the CPU has to support: the SIMD backends
+// call `target_feature` functions, so only detected backends may be passed.
+fn keccak256_batch_with(backend: Backend, messages: &[&[u8]]) -> Vec<[u8; 32]> {
+    let blocks: Vec<Block> = messages.iter().map(|message| pad(message)).collect();
+    let mut out = vec![[0u8; 32]; messages.len()];
+    keccak256_blocks_with(backend, &blocks, &mut out);
+    out
+}
+
+/// Keccak-256 of already padded blocks into `out`, for callers that keep blocks around and
+/// only update the lanes that change.
+pub fn keccak256_blocks(blocks: &[Block], out: &mut [[u8; 32]]) {
+    keccak256_blocks_with(backend(), blocks, out)
+}
+
+fn keccak256_blocks_with(backend: Backend, blocks: &[Block], out: &mut [[u8; 32]]) {
+    assert_eq!(blocks.len(), out.len());
+    let full = blocks.len() / backend.lanes() * backend.lanes();
+    backend.hash_blocks(&blocks[..full], &mut out[..full]);
+    // the rest does not fill all lanes
+    Backend::Portable.hash_blocks(&blocks[full..], &mut out[full..]);
+}
+
+/// Keccak padding (0x01 ... 0x80) of a message of at most [`MAX_LEN`] bytes.
+pub fn pad(message: &[u8]) -> Block {
+    assert!(
+        message.len() <= MAX_LEN,
+        "Message does not fit into one block"
+    );
+    let mut bytes = [0u8; RATE];
+    bytes[..message.len()].copy_from_slice(message);
+    bytes[message.len()] ^= 0x01;
+    bytes[RATE - 1] ^= 0x80;
+    let mut block = [0u64; RATE_LANES];
+    for (lane, chunk) in block.iter_mut().zip(bytes.chunks_exact(8)) {
+        *lane = u64::from_le_bytes(chunk.try_into().unwrap());
+    }
+    block
+}
+
+// The first 4 lanes of the state are the hash.
+fn squeeze(lanes: [u64; 4]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    for (chunk, lane) in hash.chunks_exact_mut(8).zip(lanes) {
+        chunk.copy_from_slice(&lane.to_le_bytes());
+    }
+    hash
+}
+
+// the shared round code assigns `a = xor!(a, b)`, which is `a ^= b` here
+#[allow(clippy::assign_op_pattern)]
+fn portable(block: &[u64; RATE_LANES]) -> [u8; 32] {
+    macro_rules! xor {
+        ($a:expr, $b:expr) => {
+            $a ^ $b
+        };
+    }
+    macro_rules! andnot {
+        ($a:expr, $b:expr) => {
+            !$a & $b
+        };
+    }
+    macro_rules! rotl {
+        ($a:expr, $n:expr) => {
+            $a.rotate_left($n)
+        };
+    }
+    macro_rules! splat {
+        ($v:expr) => {
+            $v
+        };
+    }
+    let mut a = [0u64; 25];
+    a[..RATE_LANES].copy_from_slice(block);
+    keccak_f!(a, xor, andnot, rotl, splat);
+    squeeze([a[0], a[1], a[2], a[3]])
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::{squeeze, PI, RATE_LANES, RC, RHO};
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn avx2(blocks: &[[u64; RATE_LANES]; 4], out: &mut [[u8; 32]; 4]) {
+        macro_rules! xor {
+            ($a:expr, $b:expr) => {
+                _mm256_xor_si256($a, $b)
+            };
+        }
+        macro_rules! andnot {
+            ($a:expr, $b:expr) => {
+                _mm256_andnot_si256($a, $b)
+            };
+        }
+        macro_rules! rotl {
+            ($a:expr, $n:expr) => {{
+                let a = $a;
+                let n = $n as i64;
+                _mm256_or_si256(
+                    _mm256_sllv_epi64(a, _mm256_set1_epi64x(n)),
+                    _mm256_srlv_epi64(a, _mm256_set1_epi64x(64 - n)),
+                )
+            }};
+        }
+        macro_rules! splat {
+            ($v:expr) => {
+                _mm256_set1_epi64x($v as i64)
+            };
+        }
+        let mut a = [_mm256_setzero_si256(); 25];
+        for (i, lane) in a.iter_mut().enumerate().take(RATE_LANES) {
+            let words = [blocks[0][i], blocks[1][i], blocks[2][i], blocks[3][i]];
+            *lane = _mm256_loadu_si256(words.as_ptr() as *const __m256i);
+        }
+        keccak_f!(a, xor, andnot, rotl, splat);
+        let mut lanes = [[0u64; 4]; 4];
+        for (i, lane) in lanes.iter_mut().enumerate() {
+            _mm256_storeu_si256(lane.as_mut_ptr() as *mut __m256i, a[i]);
+        }
+        for (k, out) in out.iter_mut().enumerate() {
+            *out = squeeze([lanes[0][k], lanes[1][k], lanes[2][k], lanes[3][k]]);
+        }
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub(super) unsafe fn avx512(blocks: &[[u64; RATE_LANES]; 8], out: &mut [[u8; 32]; 8]) {
+        macro_rules! xor {
+            ($a:expr, $b:expr) => {
+                _mm512_xor_si512($a, $b)
+            };
+        }
+        macro_rules! andnot {
+            ($a:expr, $b:expr) => {
+                _mm512_andnot_si512($a, $b)
+            };
+        }
+        macro_rules! rotl {
+            ($a:expr, $n:expr) => {
+                _mm512_rolv_epi64($a, _mm512_set1_epi64($n as i64))
+            };
+        }
+        macro_rules! splat {
+            ($v:expr) => {
+                _mm512_set1_epi64($v as i64)
+            };
+        }
+        let mut a = [_mm512_setzero_si512(); 25];
+        for (i, lane) in a.iter_mut().enumerate().take(RATE_LANES) {
+            let words: [u64; 8] = std::array::from_fn(|k| blocks[k][i]);
+            *lane = _mm512_loadu_si512(words.as_ptr() as *const _);
+        }
+        keccak_f!(a, xor, andnot, rotl, splat);
+        let mut lanes = [[0u64; 8]; 4];
+        for (i, lane) in lanes.iter_mut().enumerate() {
+            _mm512_storeu_si512(lane.as_mut_ptr() as *mut _, a[i]);
+        }
+        for (k, out) in out.iter_mut().enumerate() {
+            *out = squeeze([lanes[0][k], lanes[1][k], lanes[2][k], lanes[3][k]]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_keccak::{Hasher, Keccak};
+
+    fn reference(message: &[u8]) -> [u8; 32] {
+        let mut keccak = Keccak::v256();
+        let mut hash = [0u8; 32];
+        keccak.update(message);
+        keccak.finalize(&mut hash);
+        hash
+    }
+
+    #[test]
+    fn test_backends_match_reference() {
+        // every length up to a full block, in batches that do not fill the last chunk
+        let messages: Vec<Vec<u8>> = (0..=MAX_LEN)
+            .map(|len| (0..len).map(|i| (i * 7 + len) as u8).collect())
+            .collect();
+        let messages: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+        let expected: Vec<_> = messages.iter().map(|message| reference(message)).collect();
+
+        let mut backends = vec![Backend::Portable];
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                backends.push(Backend::Avx2);
+            }
+            if is_x86_feature_detected!("avx512f") {
+                backends.push(Backend::Avx512);
+            }
+        }
+        for backend in backends {
+            assert_eq!(
+                keccak256_batch_with(backend, &messages),
+                expected,
+                "{}",
+                backend.name()
+            );
+            assert_eq!(keccak256_batch_with(backend, &messages[..3]), expected[..3]);
+        }
+        assert_eq!(
+            hex::encode(keccak256_batch(&[b""])[0]),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+    }
+}
@@ -0,0 +1,486 @@
+// Web3 Secret Storage (keystore v3), see
+// https://ethereum.org/en/developers/docs/data-structures-and-encoding/web3-secret-storage/
+use crate::error::{self, Error};
+use crate::eth;
+use crate::fs;
+use aes::Aes128;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tiny_keccak::{Hasher, Keccak};
+use zeroize::Zeroizing;
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+
+// Same defaults as geth's "standard" keystore parameters.
+const SCRYPT_LOG_N: u8 = 18;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+const PBKDF2_ROUNDS: u32 = 262_144;
+const DKLEN: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kdf {
+    Scrypt,
+    Pbkdf2,
+}
+
+impl Kdf {
+    pub fn parse(kdf: &str) -> Option<Kdf> {
+        match kdf {
+            "scrypt" => Some(Kdf::Scrypt),
+            "pbkdf2" => Some(Kdf::Pbkdf2),
+            _ => None,
+        }
+    }
+}
+
+/// Where the keystore password is read from.
+#[derive(Clone, PartialEq, Debug)]
+pub enum PasswordSource {
+    Prompt,
+    Env(String),
+    File(String),
+}
+
+impl PasswordSource {
+    pub fn read(&self) -> Result<Zeroizing<String>, String> {
+        self.read_with_prompt("Keystore password: ")
+    }
+
+    /// Reads the password of new keystores. A prompted password is asked for twice, the keys
+    /// are only ever written encrypted with it.
+    pub fn read_new(&self) -> Result<Zeroizing<String>, String> {
+        let password = self.read()?;
+        if *self == PasswordSource::Prompt
+            && *self.read_with_prompt("Repeat keystore password: ")? != *password
+        {
+            return Err("Passwords do not match".to_string());
+        }
+        Ok(password)
+    }
+
+    pub fn read_with_prompt(&self, prompt: &str) -> Result<Zeroizing<String>, String> {
+        let secret = match self {
+            PasswordSource::Prompt => rpassword::prompt_password(prompt).map_err(|e| e.to_string()),
+            PasswordSource::Env(var) => {
+                std::env::var(var).map_err(|_| format!("Environment variable {} is not set", var))
+            }
+            PasswordSource::File(path) => std::fs::read_to_string(path)
+                .map(|p| p.trim_end_matches(['\r', '\n']).to_string())
+                .map_err(|e| format!("Unable to read password file {}: {}", path, e)),
+        };
+        secret.map(Zeroizing::new)
+    }
+}
+
+#[derive(Clone)]
+pub struct KeystoreConfig {
+    pub dir: String,
+    pub kdf: Kdf,
+    pub password: Zeroizing<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Keystore {
+    pub address: Option<String>,
+    pub crypto: Crypto,
+    pub id: String,
+    pub version: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Crypto {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    pub ciphertext: String,
+    pub kdf: String,
+    pub kdfparams: KdfParams,
+    pub mac: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CipherParams {
+    pub iv: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum KdfParams {
+    Scrypt {
+        dklen: usize,
+        n: u32,
+        r: u32,
+        p: u32,
+        salt: String,
+    },
+    Pbkdf2 {
+        c: u32,
+        dklen: usize,
+        prf: String,
+        salt: String,
+    },
+}
+
+// A keystore that cannot be created, read or decrypted.
+fn invalid(message: impl std::fmt::Display) -> Error {
+    Error::Keystore(message.to_string())
+}
+
+fn derive_key(password: &str, params: &KdfParams) -> error::Result<Zeroizing<Vec<u8>>> {
+    let (KdfParams::Scrypt { dklen, .. } | KdfParams::Pbkdf2 { dklen, .. }) = params;
+    if *dklen < 32 {
+        return Err(invalid("Derived key length must be at least 32 bytes"));
+    }
+    match params {
+        KdfParams::Scrypt {
+            dklen,
+            n,
+            r,
+            p,
+            salt,
+        } => {
+            if !n.is_power_of_two() {
+                return Err(invalid("Invalid scrypt parameter n"));
+            }
+            let salt = hex::decode(salt).map_err(invalid)?;
+            let params =
+                scrypt::Params::new(n.trailing_zeros() as u8, *r, *p, *dklen).map_err(invalid)?;
+            let mut key = Zeroizing::new(vec![0u8; *dklen]);
+            scrypt::scrypt(password.as_bytes(), &salt, &params, &mut key).map_err(invalid)?;
+            Ok(key)
+        }
+        KdfParams::Pbkdf2 {
+            c,
+            dklen,
+            prf,
+            salt,
+        } => {
+            if prf != "hmac-sha256" {
+                return Err(invalid(format!("Unsupported pbkdf2 prf: {}", prf)));
+            }
+            let salt = hex::decode(salt).map_err(invalid)?;
+            let mut key = Zeroizing::new(vec![0u8; *dklen]);
+            pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, *c, &mut key);
+            Ok(key)
+        }
+    }
+}
+
+fn mac(derived_key: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+    let mut keccak = Keccak::v256();
+    keccak.update(&derived_key[16..32]);
+    keccak.update(ciphertext);
+    let mut mac = [0u8; 32];
+    keccak.finalize(&mut mac);
+    mac
+}
+
+// Compares without stopping at the first difference, so the time taken does not reveal how
+// much of a guessed MAC is right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).expect("OS random number generator failed");
+    bytes
+}
+
+fn uuid_v4() -> String {
+    let mut b: [u8; 16] = random_bytes();
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let h = hex::encode(b);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &h[0..8],
+        &h[8..12],
+        &h[12..16],
+        &h[16..20],
+        &h[20..32]
+    )
+}
+
+pub fn encrypt(private_key: &[u8], password: &str, kdf: Kdf) -> error::Result<Keystore> {
+    let salt = hex::encode(random_bytes::<32>());
+    let params = match kdf {
+        Kdf::Scrypt => KdfParams::Scrypt {
+            dklen: DKLEN,
+            n: 1 << SCRYPT_LOG_N,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt,
+        },
+        Kdf::Pbkdf2 => KdfParams::Pbkdf2 {
+            c: PBKDF2_ROUNDS,
+            dklen: DKLEN,
+            prf: "hmac-sha256".to_string(),
+            salt,
+        },
+    };
+    encrypt_with_params(private_key, password, params)
+}
+
+fn encrypt_with_params(
+    private_key: &[u8],
+    password: &str,
+    params: KdfParams,
+) -> error::Result<Keystore> {
+    let address = eth::private_key_address(private_key).map_err(invalid)?;
+    let derived_key = derive_key(password, &params)?;
+    let iv: [u8; 16] = random_bytes();
+
+    let mut ciphertext = private_key.to_vec();
+    Aes128Ctr::new(derived_key[..16].into(), &iv.into()).apply_keystream(&mut ciphertext);
+
+    let kdf = match params {
+        KdfParams::Scrypt { .. } => "scrypt",
+        KdfParams::Pbkdf2 { .. } => "pbkdf2",
+    };
+
+    Ok(Keystore {
+        address: Some(address),
+        crypto: Crypto {
+            cipher: "aes-128-ctr".to_string(),
+            cipherparams: CipherParams {
+                iv: hex::encode(iv),
+            },
+            ciphertext: hex::encode(&ciphertext),
+            kdf: kdf.to_string(),
+            kdfparams: params,
+            mac: hex::encode(mac(&derived_key, &ciphertext)),
+        },
+        id: uuid_v4(),
+        version: 3,
+    })
+}
+
+/// Decrypts a keystore and checks its MAC and, if present, its address field.
+/// Returns the raw private key.
+pub fn decrypt(keystore: &Keystore, password: &str) -> error::Result<Zeroizing<Vec<u8>>> {
+    if keystore.version != 3 {
+        return Err(invalid(format!(
+            "Unsupported keystore version {}",
+            keystore.version
+        )));
+    }
+    let crypto = &keystore.crypto;
+    if crypto.cipher != "aes-128-ctr" {
+        return Err(invalid(format!("Unsupported cipher: {}", crypto.cipher)));
+    }
+
+    let derived_key = derive_key(password, &crypto.kdfparams)?;
+    let ciphertext = hex::decode(&crypto.ciphertext).map_err(invalid)?;
+    let expected_mac = hex::decode(&crypto.mac).map_err(invalid)?;
+    if !constant_time_eq(&mac(&derived_key, &ciphertext), &expected_mac) {
+        return Err(invalid(
+            "Invalid password or corrupted keystore (MAC mismatch)",
+        ));
+    }
+
+    let iv = hex::decode(&crypto.cipherparams.iv).map_err(invalid)?;
+    if iv.len() != 16 {
+        return Err(invalid("Invalid iv length"));
+    }
+    let mut private_key = Zeroizing::new(ciphertext);
+    Aes128Ctr::new(derived_key[..16].into(), iv[..].into()).apply_keystream(&mut private_key);
+
+    if let Some(address) = &keystore.address {
+        let derived = eth::private_key_address(&private_key).map_err(invalid)?;
+        if derived != address.trim_start_matches("0x").to_lowercase() {
+            return Err(invalid(format!(
+                "Keystore address 0x{} does not match its private key (0x{})",
+                address, derived
+            )));
+        }
+    }
+
+    Ok(private_key)
+}
+
+/// Encrypts `private_key` and writes it to `dir` using geth's file naming. Like geth, the file
+/// is readable by the current user only.
+pub fn write_keystore(private_key: &[u8], config: &KeystoreConfig) -> error::Result<PathBuf> {
+    let keystore = encrypt(private_key, &config.password, config.kdf)?;
+    let address = keystore.address.clone().unwrap_or_default();
+    let path = Path::new(&config.dir).join(format!("UTC--{}--{}", utc_timestamp(), address));
+    let json = serde_json::to_string(&keystore).map_err(invalid)?;
+    fs::create_private_dir(Path::new(&config.dir))
+        .and_then(|_| fs::check_private_path(&path))
+        .and_then(|_| {
+            let mut file = fs::open_private(&path)?;
+            file.write_all(json.as_bytes())?;
+            file.sync_all()
+        })?;
+    Ok(path)
+}
+
+pub fn read_keystore(path: &str) -> error::Result<Keystore> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| invalid(format!("Unable to read {}: {}", path, e)))?;
+    serde_json::from_str(&content).map_err(|e| invalid(format!("Invalid keystore {}: {}", path, e)))
+}
+
+/// Decrypts the keystore at `path` and prints its checksummed address, failing if it differs
+/// from `expected_address`.
+pub fn verify(
+    path: &str,
+    password: &PasswordSource,
+    expected_address: Option<&str>,
+    show_private_key: bool,
+) -> Result<(), Error> {
+    let failed =
+        |e: &dyn std::fmt::Display| invalid(format!("Keystore verification failed: {}", e));
+    let keystore = read_keystore(path).map_err(|e| failed(&e))?;
+    let password = password.read().map_err(|e| failed(&e))?;
+    let private_key = decrypt(&keystore, &password).map_err(|e| failed(&e))?;
+    let address = eth::private_key_address(&private_key).expect("Verified above");
+    if let Some(expected) = expected_address {
+        if address != expected.trim_start_matches("0x").to_lowercase() {
+            return Err(failed(&format!(
+                "Keystore holds the key of 0x{}, expected {}",
+                eth::checksum(&address),
+                expected
+            )));
+        }
+    }
+    println!("Keystore OK");
+    println!("Address: 0x{}", eth::checksum(&address));
+    if show_private_key {
+        println!(
+            "Private key: {}",
+            *Zeroizing::new(hex::encode(&*private_key))
+        );
+    }
+    Ok(())
+}
+
+// e.g. 2026-10-19T12-30-00.000000000Z
+fn utc_timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}-{:02}-{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60,
+        now.subsec_nanos()
+    )
+}
+
+// Howard Hinnant's days-to-civil algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+    #[test]
+    fn test_decrypt_pbkdf2_test_vector() {
+        let keystore: Keystore = serde_json::from_str(
+            r#"{
+                "crypto": {
+                    "cipher": "aes-128-ctr",
+                    "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+                    "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+                    "kdf": "pbkdf2",
+                    "kdfparams": {
+                        "c": 262144,
+                        "dklen": 32,
+                        "prf": "hmac-sha256",
+                        "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+                    },
+                    "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+                },
+                "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+                "version": 3
+            }"#,
+        )
+        .unwrap();
+        let private_key = decrypt(&keystore, "testpassword").unwrap();
+        assert_eq!(hex::encode(&*private_key), PRIVATE_KEY);
+        assert!(decrypt(&keystore, "wrongpassword").is_err());
+    }
+
+    #[test]
+    fn test_scrypt_round_trip() {
+        let private_key = hex::decode(PRIVATE_KEY).unwrap();
+        let params = KdfParams::Scrypt {
+            dklen: 32,
+            n: 1 << 10,
+            r: 8,
+            p: 1,
+            salt: hex::encode(random_bytes::<32>()),
+        };
+        let keystore = encrypt_with_params(&private_key, "password", params).unwrap();
+        let json = serde_json::to_string(&keystore).unwrap();
+        let keystore: Keystore = serde_json::from_str(&json).unwrap();
+        assert_eq!(keystore.crypto.kdf, "scrypt");
+        assert_eq!(
+            keystore.address.as_deref(),
+            Some("008aeeda4d805471df9b2a5b0f38a0c3bcba786b")
+        );
+        assert_eq!(*decrypt(&keystore, "password").unwrap(), private_key);
+        assert!(decrypt(&keystore, "Password").is_err());
+    }
+
+    #[test]
+    fn test_write_keystore() {
+        let dir = fs::tests::private_dir().join("keys");
+        let config = KeystoreConfig {
+            dir: dir.display().to_string(),
+            kdf: Kdf::Pbkdf2,
+            password: Zeroizing::new("password".to_string()),
+        };
+        let private_key = hex::decode(PRIVATE_KEY).unwrap();
+        let path = write_keystore(&private_key, &config).unwrap();
+        let keystore = read_keystore(path.to_str().unwrap()).unwrap();
+        assert_eq!(*decrypt(&keystore, "password").unwrap(), private_key);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&path), 0o600);
+            assert_eq!(mode(&dir), 0o700);
+        }
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"mac", b"mac"));
+        assert!(!constant_time_eq(b"mac", b"max"));
+        assert!(!constant_time_eq(b"mac", b"ma"));
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(20_745), (2026, 10, 19));
+    }
+}
@@ -1,8 +1,21 @@
+pub mod bench;
 pub mod conf;
 pub mod create2;
+pub mod entropy;
+pub mod error;
 pub mod eth;
 pub mod fs;
+pub mod hd;
+pub mod hooks;
+pub mod keccak;
+pub mod keystore;
+pub mod output;
+pub mod report;
+pub mod sched;
+pub mod splitkey;
+pub mod store;
 pub mod strategy;
 mod test;
 pub mod thread;
 pub mod utils;
+pub mod verify;
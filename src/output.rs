@@ -0,0 +1,531 @@
+use crate::conf::config::AppConfig;
+use crate::create2;
+use crate::eth::{checksum, Wallet};
+use crate::fs;
+use crate::hooks;
+use crate::strategy::Strategy;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Ndjson,
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn parse(format: &str) -> Option<OutputFormat> {
+        match format {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "ndjson" => Some(OutputFormat::Ndjson),
+            "csv" => Some(OutputFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+const CSV_HEADER: &str = "address,private_key,key_offset,mnemonic,derivation_path,salt,deployer,init_code_hash,contract_address,nonce,hook_flags,keystore,score,pattern,strategy,entropy,timestamp";
+
+/// A single search result, as written to stdout and to the output file.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Record {
+    pub address: Option<String>,
+    pub private_key: Option<Zeroizing<String>>,
+    pub key_offset: Option<String>,
+    pub mnemonic: Option<Zeroizing<String>>,
+    pub derivation_path: Option<String>,
+    pub salt: Option<String>,
+    pub deployer: Option<String>,
+    pub init_code_hash: Option<String>,
+    pub contract_address: Option<String>,
+    pub nonce: Option<u64>,
+    pub hook_flags: Option<String>,
+    pub keystore: Option<String>,
+    pub score: u64,
+    pub pattern: String,
+    pub strategy: String,
+    pub entropy: String,
+    pub timestamp: u64,
+}
+
+impl Record {
+    /// `address` is the address the score was computed on: the contract address for contract
+    /// and create2 searches, the account address otherwise.
+    pub fn new(
+        wallet: &Wallet,
+        config: &AppConfig,
+        salt: [u8; 32],
+        nonce: u64,
+        address: &str,
+        score: u64,
+    ) -> Record {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut record = Record {
+            address: None,
+            private_key: None,
+            key_offset: None,
+            mnemonic: None,
+            derivation_path: None,
+            salt: None,
+            deployer: None,
+            init_code_hash: None,
+            contract_address: None,
+            nonce: None,
+            hook_flags: None,
+            keystore: None,
+            score,
+            pattern: config.pattern.clone(),
+            strategy: config.strategy.name().to_string(),
+            entropy: config.entropy.name().to_string(),
+            timestamp,
+        };
+
+        if config.create2 {
+            let bytecode_hash = create2::init_code_hash(config);
+            record.salt = Some(format!("0x{}", hex::encode(salt)));
+            record.deployer = Some(format!("0x{}", checksum(&hex::encode(config.deployer))));
+            record.init_code_hash = Some(format!("0x{}", hex::encode(bytecode_hash)));
+            record.contract_address = Some(format!("0x{}", checksum(address)));
+        } else {
+            record.address = Some(format!("0x{}", checksum(&wallet.public_key)));
+            if config.split_key.is_some() {
+                record.key_offset = Some(wallet.private_key.to_hex().to_string());
+            } else {
+                record.private_key = Some(wallet.private_key.to_hex());
+            }
+            if config.contract {
+                record.contract_address = Some(format!("0x{}", checksum(address)));
+                record.nonce = Some(nonce);
+            }
+        }
+
+        if let (Strategy::Hook, Some(contract_address)) =
+            (&config.strategy, &record.contract_address)
+        {
+            record.hook_flags =
+                hooks::address_flags(contract_address).map(|flags| format!("{:#06x}", flags));
+        }
+
+        record
+    }
+
+    fn to_csv(&self) -> String {
+        let opt = |v: &Option<String>| v.as_deref().map(csv_escape).unwrap_or_default();
+        [
+            opt(&self.address),
+            self.private_key
+                .as_deref()
+                .map(|k| csv_escape(k))
+                .unwrap_or_default(),
+            opt(&self.key_offset),
+            self.mnemonic
+                .as_deref()
+                .map(|m| csv_escape(m))
+                .unwrap_or_default(),
+            opt(&self.derivation_path),
+            opt(&self.salt),
+            opt(&self.deployer),
+            opt(&self.init_code_hash),
+            opt(&self.contract_address),
+            self.nonce.map(|n| n.to_string()).unwrap_or_default(),
+            opt(&self.hook_flags),
+            opt(&self.keystore),
+            self.score.to_string(),
+            csv_escape(&self.pattern),
+            csv_escape(&self.strategy),
+            csv_escape(&self.entropy),
+            self.timestamp.to_string(),
+        ]
+        .join(",")
+    }
+
+    /// The record without its private key, split-key offset and mnemonic, for stdout.
+    pub fn without_secrets(&self) -> Record {
+        Record {
+            private_key: None,
+            key_offset: None,
+            mnemonic: None,
+            ..self.clone()
+        }
+    }
+
+    /// The address the pattern was matched against: the contract address of contract
+    /// searches, the address of the key otherwise.
+    pub fn matched_address(&self) -> &str {
+        self.contract_address
+            .as_deref()
+            .or(self.address.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The secret needed to use the result: the private key, the split-key offset, the salt
+    /// for create2 or the keystore file holding the private key.
+    pub fn secret(&self) -> &str {
+        self.private_key
+            .as_deref()
+            .map(String::as_str)
+            .or(self.key_offset.as_deref())
+            .or(self.salt.as_deref())
+            .or(self.keystore.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Formats a record for stdout. `first` is set for the first record of a run, so CSV
+/// output starts with a header.
+pub fn format_record(record: &Record, format: OutputFormat, first: bool) -> String {
+    match format {
+        OutputFormat::Text => {
+            let mut out = String::from("--------------\n");
+            out.push_str(&format!("SCORE: {}\n", record.score));
+            if let Some(salt) = &record.salt {
+                out.push_str(&format!("Found salt: {}\n", &salt[2..]));
+            }
+            if let Some(private_key) = &record.private_key {
+                out.push_str(&format!("Private key: {}\n", private_key.as_str()));
+            }
+            if let Some(key_offset) = &record.key_offset {
+                out.push_str(&format!("Key offset: {}\n", key_offset));
+            }
+            if let Some(mnemonic) = &record.mnemonic {
+                out.push_str(&format!("Mnemonic: {}\n", mnemonic.as_str()));
+            }
+            if let Some(path) = &record.derivation_path {
+                out.push_str(&format!("Derivation path: {}\n", path));
+            }
+            if let Some(address) = &record.address {
+                out.push_str(&format!("Address: {}\n", address));
+            }
+            if let Some(contract_address) = &record.contract_address {
+                out.push_str(&format!("Contract address: {}\n", contract_address));
+            }
+            if let Some(nonce) = record.nonce.filter(|&n| n != 0) {
+                out.push_str(&format!("Deployer nonce: {}\n", nonce));
+            }
+            if let Some(flags) = &record.hook_flags {
+                let names = hooks::flag_names(u16::from_str_radix(&flags[2..], 16).unwrap_or(0));
+                out.push_str(&format!("Hook flags: {} ({})\n", flags, names.join(", ")));
+            }
+            out.push_str(&format!("Entropy: {}\n", record.entropy));
+            if let Some(keystore) = &record.keystore {
+                out.push_str(&format!("Keystore: {}\n", keystore));
+            }
+            out.push_str("--------------\n\n");
+            out
+        }
+        OutputFormat::Json => serde_json::to_string_pretty(record).expect("Unable to serialize"),
+        OutputFormat::Ndjson => serde_json::to_string(record).expect("Unable to serialize"),
+        OutputFormat::Csv if first => format!("{}\n{}", CSV_HEADER, record.to_csv()),
+        OutputFormat::Csv => record.to_csv(),
+    }
+}
+
+static PRINTED_RECORD: AtomicBool = AtomicBool::new(false);
+
+/// Prints a record to stdout. A search may print any number of records, so JSON records are
+/// printed one compact object per line like NDJSON, which scripts can read as they arrive.
+pub fn print_record(record: &Record, format: OutputFormat) {
+    let first = !PRINTED_RECORD.swap(true, Ordering::Relaxed);
+    let format = match format {
+        OutputFormat::Json => OutputFormat::Ndjson,
+        format => format,
+    };
+    println!("{}", format_record(record, format, first));
+}
+
+// Entry of a record in an output file other than JSON. A text entry is one line with the
+// matched address followed by its secret (a mnemonic is followed by its derivation path), so
+// results printed without secrets can be looked up in the file. CSV files start with a header.
+fn file_entry(record: &Record, format: OutputFormat, first: bool) -> String {
+    let address = record.matched_address();
+    match (format, &record.mnemonic, &record.derivation_path) {
+        (OutputFormat::Text, Some(mnemonic), Some(derivation_path)) => {
+            format!("{} {} {}\n", address, mnemonic.as_str(), derivation_path)
+        }
+        (OutputFormat::Text, _, _) => format!("{} {}\n", address, record.secret()),
+        (format, _, _) => format!("{}\n", format_record(record, format, first)),
+    }
+}
+
+// The output file, only ever written by the writer thread.
+struct OutputFile {
+    path: PathBuf,
+    format: OutputFormat,
+    max_size: Option<u64>,
+    file: File,
+    size: u64,
+    // with JSON, where the array ends after its last record and whether it holds any
+    json_end: Option<(u64, bool)>,
+}
+
+impl OutputFile {
+    fn open(path: &Path, format: OutputFormat, max_size: Option<u64>) -> io::Result<OutputFile> {
+        fs::check_private_path(path)?;
+        let mut file = match format {
+            OutputFormat::Json => fs::open_private_rw(path)?,
+            _ => fs::open_private(path)?,
+        };
+        let size = file.metadata()?.len();
+        let json_end = match format {
+            OutputFormat::Json if size > 0 => Some(json_end(&mut file, size).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} does not end with a JSON array", path.display()),
+                )
+            })?),
+            _ => None,
+        };
+        Ok(OutputFile {
+            path: path.to_path_buf(),
+            format,
+            max_size,
+            file,
+            size,
+            json_end,
+        })
+    }
+
+    // Starts a new file when `growth` more bytes would exceed the maximum size.
+    fn rotate_for(&mut self, growth: u64) -> io::Result<()> {
+        match self.max_size {
+            Some(max_size) if self.size > 0 && self.size + growth > max_size => {
+                fs::rotate(&self.path)?;
+                *self = OutputFile::open(&self.path, self.format, self.max_size)?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Writes a whole record with a single write and syncs it to disk.
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            return self.write_json(record);
+        }
+        self.rotate_for(file_entry(record, self.format, false).len() as u64)?;
+        let entry = file_entry(record, self.format, self.size == 0);
+        self.file.write_all(entry.as_bytes())?;
+        self.file.sync_data()?;
+        self.size += entry.len() as u64;
+        Ok(())
+    }
+
+    // A JSON file holds a single array. A record is written over the end of the array, which
+    // is closed again after it, so the file is never rewritten or kept in memory.
+    fn write_json(&mut self, record: &Record) -> io::Result<()> {
+        let entry = format!(
+            "  {}",
+            serde_json::to_string_pretty(record)?.replace('\n', "\n  ")
+        );
+        self.rotate_for(entry.len() as u64 + 4)?;
+        let (offset, content) = match self.json_end {
+            None => (0, format!("[\n{}\n]\n", entry)),
+            Some((end, false)) => (end, format!("\n{}\n]\n", entry)),
+            Some((end, true)) => (end, format!(",\n{}\n]\n", entry)),
+        };
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(content.as_bytes())?;
+        let size = offset + content.len() as u64;
+        if size < self.size {
+            self.file.set_len(size)?;
+        }
+        self.file.sync_data()?;
+        self.size = size;
+        self.json_end = Some((size - "\n]\n".len() as u64, true));
+        Ok(())
+    }
+}
+
+// Finds the end of the last record of the JSON array closing a file of `size` bytes, and
+// whether there is one. Only the tail of the file is read, it holds secrets.
+fn json_end(file: &mut File, size: u64) -> Option<(u64, bool)> {
+    let start = size.saturating_sub(64);
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(start)).ok()?;
+    file.read_to_end(&mut tail).ok()?;
+    let body = tail.trim_ascii_end().strip_suffix(b"]")?.trim_ascii_end();
+    let end = start + body.len() as u64;
+    Some((end, body.last() != Some(&b'[')))
+}
+
+/// Appends records to an output file from a thread of its own, which is the only one writing
+/// it. The file is created readable by the current user only, every record is written whole
+/// and synced before the next one, and with a maximum size full files are moved to
+/// `path.1`, `path.2`, ...
+pub struct RecordWriter {
+    tx: Option<mpsc::Sender<Record>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RecordWriter {
+    /// Opens the file at `path` right away, so that a location other users can access is
+    /// refused before the search starts.
+    pub fn spawn(path: &str, format: OutputFormat, max_size: Option<u64>) -> io::Result<Self> {
+        let mut file = OutputFile::open(Path::new(path), format, max_size)?;
+        let (tx, rx) = mpsc::channel::<Record>();
+        let thread = std::thread::spawn(move || {
+            for record in rx {
+                if let Err(e) = file.write(&record) {
+                    eprintln!("Unable to write result to {}: {}", file.path.display(), e);
+                }
+            }
+        });
+        Ok(RecordWriter {
+            tx: Some(tx),
+            thread: Some(thread),
+        })
+    }
+
+    pub fn write(&self, record: &Record) {
+        if let Some(tx) = &self.tx {
+            _ = tx.send(record.clone());
+        }
+    }
+
+    /// Waits until every record is written.
+    pub fn close(mut self) {
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        drop(self.tx.take());
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
+impl Drop for RecordWriter {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn record() -> Record {
+        Record {
+            address: Some("0xdeadE47Af1E325c4B5905818EC43F6bD44e18aCb".to_string()),
+            private_key: Some(Zeroizing::new(
+                "c2a6ce05488e5bacb8e4c2edc2bec4d8ae4572cbbeddb3564b52e2ca45887167".to_string(),
+            )),
+            key_offset: None,
+            mnemonic: None,
+            derivation_path: None,
+            salt: None,
+            deployer: None,
+            init_code_hash: None,
+            contract_address: None,
+            nonce: None,
+            hook_flags: None,
+            keystore: None,
+            score: 4,
+            pattern: "dead".to_string(),
+            strategy: "startswith".to_string(),
+            entropy: "os".to_string(),
+            timestamp: 1,
+        }
+    }
+
+    #[test]
+    fn test_text_file_entry() {
+        let mut record = record();
+        assert_eq!(
+            file_entry(&record, OutputFormat::Text, true),
+            "0xdeadE47Af1E325c4B5905818EC43F6bD44e18aCb \
+             c2a6ce05488e5bacb8e4c2edc2bec4d8ae4572cbbeddb3564b52e2ca45887167\n"
+        );
+        record.contract_address = Some("0xdEAd000000000000000000000000000000000000".to_string());
+        record.private_key = None;
+        record.salt = Some("0x01".to_string());
+        assert_eq!(
+            file_entry(&record, OutputFormat::Text, true),
+            "0xdEAd000000000000000000000000000000000000 0x01\n"
+        );
+    }
+
+    #[test]
+    fn test_format_csv() {
+        let out = format_record(&record(), OutputFormat::Csv, true);
+        let mut lines = out.lines();
+        assert_eq!(lines.next().unwrap(), CSV_HEADER);
+        assert_eq!(
+            lines.next().unwrap(),
+            "0xdeadE47Af1E325c4B5905818EC43F6bD44e18aCb,c2a6ce05488e5bacb8e4c2edc2bec4d8ae4572cbbeddb3564b52e2ca45887167,,,,,,,,,,,4,dead,startswith,os,1"
+        );
+    }
+
+    #[test]
+    fn test_append_json_records() {
+        let dir = crate::fs::tests::private_dir();
+        let path = dir.join("pks.json").display().to_string();
+        for _ in 0..2 {
+            let writer = RecordWriter::spawn(&path, OutputFormat::Json, None).unwrap();
+            writer.write(&record());
+            writer.close();
+        }
+        let content = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<serde_json::Value> = serde_json::from_str(&content).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["pattern"], "dead");
+        assert!(records[1]["salt"].is_null());
+        // appended in place, laid out like the whole array written at once
+        assert_eq!(
+            content,
+            serde_json::to_string_pretty(&[record(), record()]).unwrap() + "\n"
+        );
+
+        // an empty array is filled as well
+        std::fs::write(&path, "[]\n").unwrap();
+        let writer = RecordWriter::spawn(&path, OutputFormat::Json, None).unwrap();
+        writer.write(&record());
+        writer.close();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<serde_json::Value> = serde_json::from_str(&content).unwrap();
+        assert_eq!(records.len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_records() {
+        let dir = crate::fs::tests::private_dir();
+        let path = dir.join("pks.csv").display().to_string();
+        // room for the header and two records
+        let entry = format_record(&record(), OutputFormat::Csv, false).len() + 1;
+        let max_size = (CSV_HEADER.len() + 1 + 2 * entry) as u64;
+        let writer = RecordWriter::spawn(&path, OutputFormat::Csv, Some(max_size)).unwrap();
+        for _ in 0..3 {
+            writer.write(&record());
+        }
+        writer.close();
+        let lines = |path: &str| std::fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!(lines(&format!("{}.1", path)), 3);
+        // the new file starts with a header again
+        assert_eq!(lines(&path), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
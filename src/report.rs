@@ -0,0 +1,471 @@
+// Which results a search keeps and where they are written, the same for every strategy: a
+// strategy only provides the score of an address and the score of a complete match.
+use crate::conf::config::AppConfig;
+use crate::error::Result;
+use crate::keystore::{self, KeystoreConfig};
+use crate::output::{self, OutputFormat, Record, RecordWriter};
+use crate::store::Store;
+use crate::thread;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use zeroize::Zeroizing;
+
+/// The scores a search reports.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Threshold {
+    /// Score of a complete match, `None` for strategies that only ever improve (trailing).
+    pub complete: Option<u64>,
+    /// Every result scoring at least this much is kept, improvement or not.
+    pub min_score: Option<u64>,
+    pub continuous: bool,
+}
+
+impl Threshold {
+    pub fn new(config: &AppConfig) -> Threshold {
+        let target = thread::target_score(config);
+        Threshold {
+            complete: (target != u64::MAX).then_some(target),
+            min_score: config.min_score,
+            continuous: config.continuous,
+        }
+    }
+
+    pub fn is_complete(&self, score: u64) -> bool {
+        self.complete.is_some_and(|complete| score >= complete)
+    }
+
+    /// Whether results are only kept when they beat the best score so far. With a minimum
+    /// score every result reaching it is kept, and continuous searches keep every complete
+    /// match, unless there is none to reach.
+    pub fn tracks_best(&self) -> bool {
+        self.min_score.is_none() && (!self.continuous || self.complete.is_none())
+    }
+
+    /// Whether a result scoring `score` is kept, `best` being the best score kept so far.
+    pub fn keeps(&self, score: u64, best: u64) -> bool {
+        match (self.min_score, self.tracks_best()) {
+            (Some(min_score), _) => score >= min_score,
+            (None, true) => score > best,
+            (None, false) => self.is_complete(score),
+        }
+    }
+
+    /// Whether a result scoring `score` ends the search.
+    pub fn ends(&self, score: u64) -> bool {
+        !self.continuous && self.is_complete(score)
+    }
+}
+
+/// Collects the verified results of all workers on the main thread. Continuous searches
+/// collect their results in the output file, others print them. With `--no-print-secrets`
+/// printed results lack their secrets, which go to the output file instead. With
+/// `--keystore-dir` every reported key is only written to its keystore file.
+pub struct Reporter {
+    pub threshold: Threshold,
+    format: OutputFormat,
+    hide_secrets: bool,
+    // the output file of continuous searches and leaderboards
+    file: Option<RecordWriter>,
+    // addresses already in the output file
+    written: HashSet<String>,
+    // with `--store`, together with the name of the search
+    store: Option<(Store, &'static str)>,
+    keystore: Option<KeystoreConfig>,
+    /// Best score reported so far.
+    pub best: u64,
+    /// Number of results written.
+    pub reported: u64,
+    max_results: Option<u64>,
+}
+
+impl Reporter {
+    pub fn new(config: &AppConfig) -> Result<Reporter> {
+        let file = match config.continuous || config.top.is_some() || config.hide_secrets {
+            true => Some(RecordWriter::spawn(
+                &config.pkstorage_path,
+                config.output_format,
+                config.max_file_size,
+            )?),
+            false => None,
+        };
+        let store = match &config.store {
+            Some(path) => Some((Store::open(path)?, config.mode())),
+            None => None,
+        };
+        Ok(Reporter {
+            threshold: Threshold::new(config),
+            format: config.output_format,
+            hide_secrets: config.hide_secrets,
+            file,
+            written: HashSet::new(),
+            store,
+            keystore: config.keystore.clone(),
+            best: 0,
+            reported: 0,
+            max_results: config.max_results,
+        })
+    }
+
+    // Adds `record` to the store, false if its address was stored before.
+    fn store(&self, record: &Record) -> bool {
+        match &self.store {
+            Some((store, mode)) => store.insert(record, mode).unwrap_or_else(|e| {
+                eprintln!("Unable to store result: {}", e);
+                true
+            }),
+            None => true,
+        }
+    }
+
+    // Writes the keystore of a result holding a private key, which then holds the path of the
+    // keystore instead of its private key and mnemonic.
+    fn seal(&self, record: &mut Record) {
+        let (Some(keystore), Some(private_key)) = (&self.keystore, &record.private_key) else {
+            return;
+        };
+        let private_key = Zeroizing::new(hex::decode(private_key.as_str()).unwrap_or_default());
+        match keystore::write_keystore(&private_key, keystore) {
+            Ok(path) => {
+                record.keystore = Some(path.display().to_string());
+                record.private_key = None;
+                record.mnemonic = None;
+            }
+            Err(e) => eprintln!("Unable to write keystore: {}", e),
+        }
+    }
+
+    /// Writes `record` if it is still kept, results of different threads arrive out of
+    /// order. Returns whether the search is done, after a complete match or once
+    /// `--max-results` are written.
+    pub fn report(&mut self, record: &mut Record) -> bool {
+        let complete = self.threshold.ends(record.score);
+        let kept = self.threshold.keeps(record.score, self.best) || complete;
+        if kept {
+            self.seal(record);
+        }
+        // an address found again is not reported twice
+        if kept && self.store(record) {
+            self.best = self.best.max(record.score);
+            self.reported += 1;
+            match self.threshold.continuous {
+                true if self.file.is_some() => self.write(record),
+                _ => self.print(record),
+            }
+        }
+        complete || self.max_results.is_some_and(|max| self.reported >= max)
+    }
+
+    /// Shows the leaderboard, best first, and appends the entries that are not in the output
+    /// file yet, which only continuous searches write otherwise.
+    pub fn finish(&mut self, leaderboard: Leaderboard) {
+        let records = leaderboard.into_records();
+        if self.format == OutputFormat::Text {
+            println!("\nTop {} addresses:", records.len());
+        }
+        for mut record in records {
+            self.seal(&mut record);
+            match self.hide_secrets {
+                true => output::print_record(&record.without_secrets(), self.format),
+                false => output::print_record(&record, self.format),
+            }
+            if !self.written.contains(record.matched_address()) {
+                self.write(&record);
+            }
+            self.store(&record);
+        }
+    }
+
+    fn write(&mut self, record: &Record) {
+        if let Some(file) = &self.file {
+            file.write(record);
+            self.written.insert(record.matched_address().to_string());
+        }
+    }
+
+    fn print(&mut self, record: &Record) {
+        match self.hide_secrets {
+            true if self.file.is_some() => {
+                output::print_record(&record.without_secrets(), self.format);
+                self.write(record);
+            }
+            _ => output::print_record(record, self.format),
+        }
+    }
+
+    /// Waits until the output file has every result.
+    pub fn close(self) {
+        if let Some(file) = self.file {
+            file.close();
+        }
+    }
+}
+
+/// Rank of an address on the leaderboard: the score, ties broken by the number of zero
+/// bytes of the lowercase hex `address`, which make calls to it cheaper.
+pub fn rank(score: u64, address: &str) -> u64 {
+    let zero_bytes = address
+        .trim_start_matches("0x")
+        .as_bytes()
+        .chunks(2)
+        .filter(|byte| byte == b"00")
+        .count() as u64;
+    score.saturating_mul(256).saturating_add(zero_bytes)
+}
+
+// A leaderboard entry, ordered by rank and then by address so that equal ranks sort the same
+// on every run.
+struct Ranked {
+    rank: u64,
+    address: String,
+    record: Record,
+}
+
+impl Ranked {
+    fn key(&self) -> (u64, Reverse<&str>) {
+        (self.rank, Reverse(&self.address))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// The best `size` results of a search across all threads, including the near misses that
+/// did not beat the best score when they were found.
+pub struct Leaderboard {
+    size: usize,
+    // a min-heap, the worst entry is the one to drop
+    entries: BinaryHeap<Reverse<Ranked>>,
+}
+
+impl Leaderboard {
+    pub fn new(size: usize) -> Leaderboard {
+        Leaderboard {
+            size,
+            entries: BinaryHeap::with_capacity(size + 1),
+        }
+    }
+
+    /// Rank a result has to beat to get on the board, 0 until it is full.
+    pub fn floor(&self) -> u64 {
+        match self.entries.len() < self.size {
+            true => 0,
+            false => self.entries.peek().map_or(0, |entry| entry.0.rank),
+        }
+    }
+
+    pub fn offer(&mut self, record: &Record) {
+        let address = record.matched_address().to_lowercase();
+        let address = address.trim_start_matches("0x").to_string();
+        self.entries.push(Reverse(Ranked {
+            rank: rank(record.score, &address),
+            address,
+            record: record.clone(),
+        }));
+        if self.entries.len() > self.size {
+            self.entries.pop();
+        }
+    }
+
+    /// The entries, best first.
+    pub fn into_records(self) -> Vec<Record> {
+        self.entries
+            .into_sorted_vec()
+            .into_iter()
+            .map(|entry| entry.0.record)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::Strategy;
+
+    #[test]
+    fn test_threshold() {
+        let mut config = AppConfig::with_pattern("dead".to_string(), Strategy::Startswith, false);
+        let threshold = Threshold::new(&config);
+        assert_eq!(threshold.complete, Some(4));
+        assert!(threshold.keeps(2, 1) && !threshold.keeps(2, 2));
+        assert!(threshold.ends(4));
+
+        config.continuous = true;
+        let threshold = Threshold::new(&config);
+        assert!(!threshold.keeps(3, 0) && threshold.keeps(4, 4));
+        assert!(!threshold.ends(4));
+
+        // trailing has no complete match, continuous searches keep every improvement
+        let config = AppConfig {
+            continuous: true,
+            ..AppConfig::with_pattern("0".to_string(), Strategy::Trailing, false)
+        };
+        let threshold = Threshold::new(&config);
+        assert_eq!(threshold.complete, None);
+        assert!(threshold.keeps(5, 4) && !threshold.keeps(4, 4));
+        assert!(!threshold.ends(u64::MAX));
+
+        // a minimum score keeps ties and worse results above it
+        let config = AppConfig {
+            min_score: Some(3),
+            ..config
+        };
+        let threshold = Threshold::new(&config);
+        assert!(!threshold.tracks_best());
+        assert!(threshold.keeps(3, 9) && !threshold.keeps(2, 0));
+    }
+
+    #[test]
+    fn test_leaderboard() {
+        let mut board = Leaderboard::new(2);
+        let entries = [
+            (3, "0xdead000000000000000000000000000000000001"),
+            (4, "0xdead111111111111111111111111111111111111"),
+            (3, "0xdead000011111111111111111111111111111111"),
+            (3, "0xdead000000000000000000000000000000000002"),
+            (1, "0x0000000000000000000000000000000000000000"),
+        ];
+        for (score, address) in entries {
+            let mut record = crate::output::tests::record();
+            record.score = score;
+            record.address = Some(address.to_string());
+            board.offer(&record);
+        }
+        assert_eq!(
+            board.floor(),
+            rank(3, "dead000000000000000000000000000000000001")
+        );
+        let addresses: Vec<_> = board
+            .into_records()
+            .into_iter()
+            .map(|record| record.address.unwrap())
+            .collect();
+        // the tie on score and zero bytes goes to the lower address
+        assert_eq!(
+            addresses,
+            [
+                "0xdead111111111111111111111111111111111111",
+                "0xdead000000000000000000000000000000000001"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_results() {
+        let config = AppConfig {
+            min_score: Some(1),
+            max_results: Some(2),
+            // results written to a sink of their own
+            continuous: true,
+            pkstorage_path: crate::fs::tests::private_dir()
+                .join("pks.txt")
+                .display()
+                .to_string(),
+            ..AppConfig::with_pattern("dead".to_string(), Strategy::Startswith, false)
+        };
+        let mut reporter = Reporter::new(&config).unwrap();
+        let mut record = crate::output::tests::record();
+        record.score = 2;
+        assert!(!reporter.report(&mut record));
+        record.score = 1;
+        assert!(reporter.report(&mut record));
+        assert_eq!(reporter.best, 2);
+        reporter.close();
+        let written = std::fs::read_to_string(&config.pkstorage_path).unwrap();
+        assert_eq!(written.lines().count(), 2);
+        let dir = std::path::Path::new(&config.pkstorage_path)
+            .parent()
+            .unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_leaderboard_written_once() {
+        let config = AppConfig {
+            min_score: Some(3),
+            continuous: true,
+            top: Some(2),
+            pkstorage_path: crate::fs::tests::private_dir()
+                .join("pks.txt")
+                .display()
+                .to_string(),
+            ..AppConfig::with_pattern("dead".to_string(), Strategy::Startswith, false)
+        };
+        let mut reporter = Reporter::new(&config).unwrap();
+        let mut leaderboard = Leaderboard::new(2);
+        let mut reported = crate::output::tests::record();
+        reporter.report(&mut reported);
+        leaderboard.offer(&reported);
+        // a near miss that only the leaderboard has
+        let mut missed = crate::output::tests::record();
+        missed.address = Some("0xdea0000000000000000000000000000000000000".to_string());
+        missed.score = 2;
+        reporter.report(&mut missed);
+        leaderboard.offer(&missed);
+        reporter.finish(leaderboard);
+        reporter.close();
+        let written = std::fs::read_to_string(&config.pkstorage_path).unwrap();
+        assert_eq!(written.lines().count(), 2);
+        let dir = std::path::Path::new(&config.pkstorage_path)
+            .parent()
+            .unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_keystore_results() {
+        let dir = crate::fs::tests::private_dir();
+        let config = AppConfig {
+            continuous: true,
+            pkstorage_path: dir.join("pks.txt").display().to_string(),
+            keystore: Some(KeystoreConfig {
+                dir: dir.join("keys").display().to_string(),
+                kdf: keystore::Kdf::Pbkdf2,
+                password: Zeroizing::new("password".to_string()),
+            }),
+            ..AppConfig::with_pattern("dead".to_string(), Strategy::Startswith, false)
+        };
+        let mut reporter = Reporter::new(&config).unwrap();
+        let mut record = crate::output::tests::record();
+        let private_key = record.private_key.clone().unwrap();
+        reporter.report(&mut record);
+        reporter.close();
+        // the key is only in the keystore
+        assert_eq!(record.private_key, None);
+        let path = record.keystore.unwrap();
+        let written = std::fs::read_to_string(&config.pkstorage_path).unwrap();
+        assert_eq!(written, format!("{} {}\n", record.address.unwrap(), path));
+        let keystore = keystore::read_keystore(&path).unwrap();
+        let decrypted = keystore::decrypt(&keystore, "password").unwrap();
+        assert_eq!(hex::encode(&*decrypted), *private_key);
+
+        // improvements printed before the complete match are sealed as well
+        let reporter = Reporter::new(&AppConfig {
+            continuous: false,
+            ..config
+        })
+        .unwrap();
+        let mut record = crate::output::tests::record();
+        record.score = 2;
+        reporter.seal(&mut record);
+        assert_eq!(record.private_key, None);
+        assert!(record.keystore.is_some());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
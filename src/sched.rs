@@ -0,0 +1,136 @@
+// Thread count, CPU affinity and priority of the search workers, and stopping them.
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Number of threads the OS reports it can run in parallel, 1 if unknown.
+pub fn available_threads() -> u32 {
+    std::thread::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(1)
+}
+
+/// Lowers the priority of the calling thread to nice 10. Threads spawned afterwards inherit
+/// it, so this is called before the workers are started.
+#[cfg(unix)]
+pub fn lower_priority() -> io::Result<()> {
+    // SAFETY: setpriority only changes the scheduling priority of the calling thread.
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 10) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn lower_priority() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "changing the priority is not supported on this platform",
+    ))
+}
+
+/// Cores the process may run on, in ascending order.
+#[cfg(target_os = "linux")]
+pub fn allowed_cores() -> io::Result<Vec<usize>> {
+    // SAFETY: an all zero cpu_set_t is an empty set and sched_getaffinity writes at most
+    // size_of::<cpu_set_t>() bytes into it.
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    if unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) } != 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((0..libc::CPU_SETSIZE as usize)
+        // SAFETY: the index is below CPU_SETSIZE.
+        .filter(|&core| unsafe { libc::CPU_ISSET(core, &set) })
+        .collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn allowed_cores() -> io::Result<Vec<usize>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "pinning threads to cores is not supported on this platform",
+    ))
+}
+
+/// Restricts the calling thread to `core`.
+#[cfg(target_os = "linux")]
+pub fn pin_to_core(core: usize) -> io::Result<()> {
+    // SAFETY: as in allowed_cores, and CPU_SET is only called with an index below
+    // CPU_SETSIZE.
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "core out of range",
+        ));
+    }
+    unsafe { libc::CPU_SET(core, &mut set) };
+    if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_to_core(_core: usize) -> io::Result<()> {
+    allowed_cores().map(|_| ())
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+    // a second Ctrl-C kills the process as usual
+    // SAFETY: signal is async-signal-safe.
+    unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+}
+
+/// Makes the first Ctrl-C set [`interrupted`] instead of killing the process, so a search
+/// can stop its workers and print what it found.
+#[cfg(unix)]
+pub fn catch_interrupt() -> io::Result<()> {
+    let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only touches an atomic and calls signal.
+    if unsafe { libc::signal(libc::SIGINT, handler) } == libc::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn catch_interrupt() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "catching Ctrl-C is not supported on this platform",
+    ))
+}
+
+/// Whether Ctrl-C was pressed since [`catch_interrupt`].
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_available_threads() {
+        assert!(available_threads() >= 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pin_to_core() {
+        let cores = allowed_cores().unwrap();
+        assert!(!cores.is_empty());
+        // pin a separate thread so the test runner keeps its affinity
+        let core = cores[cores.len() - 1];
+        let pinned = std::thread::spawn(move || {
+            pin_to_core(core).unwrap();
+            allowed_cores().unwrap()
+        });
+        assert_eq!(pinned.join().unwrap(), vec![core]);
+    }
+}
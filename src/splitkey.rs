@@ -0,0 +1,190 @@
+// Split-key vanity generation: the searcher only knows the user's public key `P` and looks
+// for an offset `k` such that the address of `P + k·G` matches. The final private key is
+// `p + k`, which only the owner of `p` can compute.
+use crate::entropy::KeyRng;
+use crate::error::Error;
+use crate::eth::{self, PrivateKey, Wallet};
+use crate::keystore::PasswordSource;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use zeroize::Zeroizing;
+
+const ONE: [u8; 32] = {
+    let mut one = [0u8; 32];
+    one[31] = 1;
+    one
+};
+
+/// Parses a hex encoded public key, compressed (33 bytes), uncompressed (65 bytes) or
+/// uncompressed without the `04` prefix (64 bytes).
+pub fn parse_public_key(input: &str) -> Result<PublicKey, Error> {
+    let invalid = |e: &dyn std::fmt::Display| Error::Input(format!("Invalid public key: {}", e));
+    let input = input.trim().trim_start_matches("0x");
+    let mut bytes = hex::decode(input).map_err(|e| invalid(&e))?;
+    if bytes.len() == 64 {
+        bytes.insert(0, 0x04);
+    }
+    PublicKey::from_slice(&bytes).map_err(|e| invalid(&e))
+}
+
+/// Walks `P + k·G`, `P + (k+1)·G`, ... from a random `k`, so each candidate costs a
+/// point addition instead of a full multiplication.
+pub struct SplitKeySearch {
+    generator: PublicKey,
+    offset: SecretKey,
+    current: PublicKey,
+}
+
+impl SplitKeySearch {
+    pub fn new(public_key: &PublicKey, rng: &mut KeyRng) -> SplitKeySearch {
+        let context = Secp256k1::new();
+        let generator = PublicKey::from_secret_key(
+            &context,
+            &SecretKey::from_slice(&ONE).expect("One is a valid key"),
+        );
+        let offset = loop {
+            let mut bytes = [0u8; 32];
+            rng.fill_bytes(&mut bytes);
+            if let Ok(offset) = SecretKey::from_slice(&bytes) {
+                break offset;
+            }
+        };
+        let offset_public = PublicKey::from_secret_key(&context, &offset);
+        let current = public_key
+            .combine(&offset_public)
+            .expect("Offset cancels out the public key");
+        SplitKeySearch {
+            generator,
+            offset,
+            current,
+        }
+    }
+
+    /// The next `count` wallets, hashing their public keys together.
+    pub fn next_wallets(&mut self, count: usize) -> Vec<Wallet> {
+        let mut offsets = Vec::with_capacity(count);
+        let mut public_keys = Vec::with_capacity(count);
+        for _ in 0..count {
+            offsets.push(PrivateKey::from_slice(&self.offset[..]).expect("Keys are 32 bytes"));
+            public_keys.push(self.current);
+            self.advance();
+        }
+        offsets
+            .into_iter()
+            .zip(eth::public_key_addresses(&public_keys))
+            .map(|(private_key, public_key)| Wallet {
+                private_key,
+                public_key,
+            })
+            .collect()
+    }
+
+    /// Returns a wallet whose private key is the current offset and whose address is the
+    /// address of the combined key, then advances to the next offset.
+    pub fn next_wallet(&mut self) -> Wallet {
+        let wallet = Wallet {
+            private_key: PrivateKey::from_slice(&self.offset[..]).expect("Keys are 32 bytes"),
+            public_key: eth::public_key_address(&self.current),
+        };
+        self.advance();
+        wallet
+    }
+
+    fn advance(&mut self) {
+        self.offset.add_assign(&ONE).expect("Offset overflowed");
+        self.current = self
+            .current
+            .combine(&self.generator)
+            .expect("Combined key is the point at infinity");
+    }
+}
+
+/// Adds the offset found by the searcher to the user's private key.
+pub fn combine(private_key: &[u8], offset: &[u8]) -> Result<PrivateKey, String> {
+    let mut key =
+        SecretKey::from_slice(private_key).map_err(|e| format!("Invalid private key: {}", e))?;
+    SecretKey::from_slice(offset).map_err(|e| format!("Invalid offset: {}", e))?;
+    let result = key
+        .add_assign(offset)
+        .map_err(|e| format!("Unable to combine keys: {}", e))
+        .map(|_| PrivateKey::from_slice(&key[..]).expect("Keys are 32 bytes"));
+    eth::erase_secret_key(&mut key);
+    result
+}
+
+/// Combines the user's private key with an offset and prints the resulting key, checking it
+/// against the expected address if one is given.
+pub fn run_combine(
+    offset: &str,
+    private_key: &PasswordSource,
+    expected_address: Option<&str>,
+) -> Result<(), Error> {
+    let result = private_key
+        .read_with_prompt("Private key: ")
+        .and_then(|private_key| {
+            let private_key = Zeroizing::new(
+                hex::decode(private_key.trim().trim_start_matches("0x"))
+                    .map_err(|e| format!("Invalid private key: {}", e))?,
+            );
+            let offset = hex::decode(offset.trim().trim_start_matches("0x"))
+                .map_err(|e| format!("Invalid offset: {}", e))?;
+            combine(&private_key, &offset)
+        })
+        .and_then(|combined| {
+            let address =
+                eth::private_key_address(combined.as_bytes()).map_err(|e| e.to_string())?;
+            if let Some(expected) = expected_address {
+                if address != expected.trim_start_matches("0x").to_lowercase() {
+                    return Err(format!(
+                        "Combined key has address 0x{}, expected {}",
+                        eth::checksum(&address),
+                        expected
+                    ));
+                }
+            }
+            Ok((combined, address))
+        });
+
+    let (combined, address) =
+        result.map_err(|e| Error::Input(format!("Unable to combine keys: {}", e)))?;
+    println!("Private key: {}", *combined.to_hex());
+    println!("Address: 0x{}", eth::checksum(&address));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_key_round_trip() {
+        let context = Secp256k1::new();
+        let (private_key, public_key) = context.generate_keypair(&mut rand::thread_rng());
+
+        let mut search = SplitKeySearch::new(&public_key, &mut KeyRng::Os);
+        search.next_wallet();
+        let wallet = search.next_wallet();
+
+        let offset = wallet.private_key.as_bytes();
+        let combined = combine(&private_key[..], offset).unwrap();
+        assert_eq!(
+            eth::private_key_address(combined.as_bytes()).unwrap(),
+            wallet.public_key
+        );
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        let context = Secp256k1::new();
+        let (_, public_key) = context.generate_keypair(&mut rand::thread_rng());
+        let compressed = hex::encode(public_key.serialize());
+        let uncompressed = hex::encode(public_key.serialize_uncompressed());
+
+        assert_eq!(parse_public_key(&compressed).unwrap(), public_key);
+        assert_eq!(
+            parse_public_key(&format!("0x{}", uncompressed)).unwrap(),
+            public_key
+        );
+        assert_eq!(parse_public_key(&uncompressed[2..]).unwrap(), public_key);
+        assert!(parse_public_key("0x1234").is_err());
+    }
+}
@@ -0,0 +1,215 @@
+// Persistent SQLite store of search results, one row per address.
+use crate::error::{Error, Result};
+use crate::output::{self, OutputFormat, Record};
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS results (
+    address TEXT PRIMARY KEY,
+    secret TEXT NOT NULL,
+    mode TEXT NOT NULL,
+    pattern TEXT NOT NULL,
+    strategy TEXT NOT NULL,
+    score INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    record TEXT NOT NULL
+)";
+
+/// Which stored results to list or export.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    pub pattern: Option<String>,
+    pub min_score: Option<u64>,
+    pub mode: Option<String>,
+    pub limit: Option<u64>,
+}
+
+/// Store file at a path, created on first use.
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Opens or creates the store, which holds secrets and so gets the same checks and
+    /// permissions as the output file.
+    pub fn open(path: &str) -> Result<Store> {
+        crate::fs::check_private_path(Path::new(path))?;
+        crate::fs::open_private(Path::new(path))?;
+        let conn = Connection::open(path)?;
+        conn.execute(SCHEMA, [])?;
+        Ok(Store { conn })
+    }
+
+    /// Stores `record` found by a `mode` search, returns false if its address is already
+    /// stored.
+    pub fn insert(&self, record: &Record, mode: &str) -> Result<bool> {
+        let json = serde_json::to_string(record).map_err(std::io::Error::from)?;
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO results
+                (address, secret, mode, pattern, strategy, score, timestamp, record)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                key(record),
+                record.secret(),
+                mode,
+                record.pattern,
+                record.strategy,
+                record.score,
+                record.timestamp,
+                json,
+            ],
+        )?;
+        Ok(inserted == 1)
+    }
+
+    /// Stored results matching `filter`, best score first and oldest first within a score.
+    pub fn query(&self, filter: &Filter) -> Result<Vec<(String, Record)>> {
+        let mut statement = self.conn.prepare(
+            "SELECT mode, record FROM results
+                WHERE (?1 IS NULL OR lower(pattern) = lower(?1))
+                AND (?2 IS NULL OR score >= ?2)
+                AND (?3 IS NULL OR mode = ?3)
+                ORDER BY score DESC, timestamp, address
+                LIMIT ?4",
+        )?;
+        let pattern = filter
+            .pattern
+            .as_deref()
+            .map(|pattern| pattern.trim_start_matches("0x"));
+        let limit = filter.limit.map_or(-1, |limit| limit as i64);
+        let rows = statement.query_map(
+            params![pattern, filter.min_score, filter.mode, limit],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )?;
+        let mut results = vec![];
+        for row in rows {
+            let (mode, json) = row?;
+            let record = serde_json::from_str(&json).map_err(std::io::Error::from)?;
+            results.push((mode, record));
+        }
+        Ok(results)
+    }
+}
+
+fn normalize(hex: &str) -> String {
+    hex.trim_start_matches("0x").to_lowercase()
+}
+
+// The address a record is unique on: the address the score is for, lowercase.
+fn key(record: &Record) -> String {
+    let address = record
+        .contract_address
+        .as_ref()
+        .or(record.address.as_ref())
+        .map_or("", String::as_str);
+    format!("0x{}", normalize(address))
+}
+
+/// Lists results as a table without their secrets for the text format, and as full records
+/// otherwise.
+pub fn format_results(results: &[(String, Record)], format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => {
+            let mut out = format!(
+                "{:<42} {:>5}  {:<8} {:<10} {:<10} {}\n",
+                "address", "score", "mode", "strategy", "pattern", "timestamp"
+            );
+            for (mode, record) in results {
+                let address = record.contract_address.as_ref().or(record.address.as_ref());
+                out.push_str(&format!(
+                    "{:<42} {:>5}  {:<8} {:<10} {:<10} {}\n",
+                    address.map_or("", String::as_str),
+                    record.score,
+                    mode,
+                    record.strategy,
+                    record.pattern,
+                    record.timestamp
+                ));
+            }
+            out
+        }
+        format => export(results, format),
+    }
+}
+
+/// All records in one document of `format`, secrets included.
+pub fn export(results: &[(String, Record)], format: OutputFormat) -> String {
+    let records: Vec<&Record> = results.iter().map(|(_, record)| record).collect();
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_string_pretty(&records).expect("Unable to serialize") + "\n"
+        }
+        format => records
+            .iter()
+            .enumerate()
+            .map(|(i, record)| output::format_record(record, format, i == 0) + "\n")
+            .collect(),
+    }
+}
+
+/// The `results` subcommand: prints the stored results matching `filter`, or writes them to
+/// `export_path`.
+pub fn run(
+    path: &str,
+    filter: &Filter,
+    format: OutputFormat,
+    export_path: Option<&str>,
+) -> Result<()> {
+    if !Path::new(path).exists() {
+        return Err(Error::Config(format!("No result store at {}", path)));
+    }
+    let results = Store::open(path)?.query(filter)?;
+    match export_path {
+        Some(export_path) => {
+            // the export holds secrets like the output file of a search
+            let export_path = Path::new(export_path);
+            crate::fs::check_private_path(export_path)?;
+            crate::fs::replace_private(export_path, export(&results, format).as_bytes())?;
+            eprintln!(
+                "Exported {} results to {}",
+                results.len(),
+                export_path.display()
+            );
+        }
+        None => print!("{}", format_results(&results, format)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store() {
+        let dir = crate::fs::tests::private_dir();
+        let path = dir.join("results.db");
+        let store = Store::open(path.to_str().unwrap()).unwrap();
+        let mut record = output::tests::record();
+        assert!(store.insert(&record, "eoa").unwrap());
+        // the same address is only stored once
+        record.score = 5;
+        assert!(!store.insert(&record, "eoa").unwrap());
+        record.address = Some("0xdead000000000000000000000000000000000000".to_string());
+        assert!(store.insert(&record, "eoa").unwrap());
+
+        let all = store.query(&Filter::default()).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].1, record);
+        let filter = Filter {
+            pattern: Some("0xDEAD".to_string()),
+            min_score: Some(5),
+            ..Filter::default()
+        };
+        assert_eq!(store.query(&filter).unwrap().len(), 1);
+        let filter = Filter {
+            mode: Some("create2".to_string()),
+            ..Filter::default()
+        };
+        assert!(store.query(&filter).unwrap().is_empty());
+
+        let csv = export(&all, OutputFormat::Csv);
+        assert_eq!(csv.lines().count(), 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
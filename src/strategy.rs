@@ -1,28 +1,22 @@
 use crate::conf::config::AppConfig;
+use crate::hooks;
 
 #[derive(Clone, PartialEq)]
 pub enum Strategy {
     Contains,
     Startswith,
     Trailing,
+    Hook,
 }
 
 pub trait Score {
-    fn score(&self, config: &AppConfig, address: &String) -> u64;
+    fn score(&self, config: &AppConfig, address: &str) -> u64;
 }
 
 impl Score for Strategy {
-    fn score(&self, config: &AppConfig, address: &String) -> u64 {
+    fn score(&self, config: &AppConfig, address: &str) -> u64 {
         match self {
-            Strategy::Startswith => {
-                let mut _s = 0;
-                for (i, c) in config.pattern.chars().enumerate() {
-                    if address.chars().nth(i).unwrap() == c {
-                        _s += 1;
-                    }
-                }
-                _s
-            }
+            Strategy::Startswith => prefix_score(&config.pattern, address),
 
             Strategy::Contains => {
                 if address.contains(&config.pattern) {
@@ -31,16 +25,46 @@ impl Score for Strategy {
                 0
             }
             Strategy::Trailing => {
-                let mut _s = 0;
-                for c in address.chars() {
-                    if c == config.pattern.chars().next().unwrap() {
-                        _s += 1;
-                    } else {
-                        break;
-                    }
+                let first = config.pattern.chars().next();
+                address.chars().take_while(|&c| Some(c) == first).count() as u64
+            }
+            Strategy::Hook => {
+                // the flags have to match exactly, the pattern is an optional vanity prefix
+                if hooks::address_flags(address) != Some(config.hook_flags) {
+                    return 0;
                 }
-                _s
+                1 + prefix_score(&config.pattern, address)
             }
         }
     }
 }
+
+impl Strategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Contains => "contains",
+            Strategy::Startswith => "startswith",
+            Strategy::Trailing => "trailing",
+            Strategy::Hook => "hook",
+        }
+    }
+
+    /// Score at which an address is a complete match, if the strategy has one.
+    pub fn target_score(&self, config: &AppConfig) -> u64 {
+        match self {
+            Strategy::Contains => 1,
+            Strategy::Startswith => config.pattern.len() as u64,
+            Strategy::Trailing => u64::MAX,
+            Strategy::Hook => config.pattern.len() as u64 + 1,
+        }
+    }
+}
+
+// Characters beyond the end of the address never match.
+fn prefix_score(pattern: &str, address: &str) -> u64 {
+    pattern
+        .chars()
+        .zip(address.chars())
+        .filter(|(p, a)| p == a)
+        .count() as u64
+}
@@ -17,10 +17,43 @@ mod tests {
         assert!(!res);
     }
 
+    #[test]
+    fn test_parse_pattern() {
+        use strategy::Strategy;
+        let parse = utils::parse_pattern;
+        assert_eq!(
+            parse("0xDeAd", &Strategy::Startswith, false).unwrap(),
+            "dead"
+        );
+        assert_eq!(parse("0XDeAd", &Strategy::Contains, true).unwrap(), "DeAd");
+        assert_eq!(parse("", &Strategy::Hook, false).unwrap(), "");
+        assert_eq!(parse("0xF", &Strategy::Trailing, true).unwrap(), "F");
+
+        assert_eq!(
+            parse("dea.d", &Strategy::Contains, false)
+                .unwrap_err()
+                .to_string(),
+            "Pattern contains '.' at position 4, addresses only contain 0-9 and a-f"
+        );
+        assert!(parse("0x", &Strategy::Startswith, false).is_err());
+        assert!(parse("0xaa", &Strategy::Trailing, false).is_err());
+        assert!(parse(&"a".repeat(41), &Strategy::Startswith, false).is_err());
+        assert!(parse(
+            &format!("0x{}", "a".repeat(40)),
+            &Strategy::Startswith,
+            false
+        )
+        .is_ok());
+        assert!(parse(&"a".repeat(37), &Strategy::Hook, false).is_err());
+    }
+
     #[test]
     fn test_calculate_difficulty() {
         let res = utils::calculate_difficulty("asd", false);
         assert_eq!(res, 16u64.pow(3));
+        // a letter has to be in the right case as well
+        let res = utils::calculate_difficulty("De0", true);
+        assert_eq!(res, 32 * 32 * 16);
     }
     #[test]
     fn test_calculate_estimated_time() {
@@ -46,7 +79,8 @@ mod tests {
     fn test_generate_eth_address() {
         let wallet = eth::Wallet::new();
         let (private, public) = (&wallet.private_key, &wallet.public_key);
-        assert_eq!(private.len(), 64);
+        assert_eq!(private.to_hex().len(), 64);
+        assert_eq!(format!("{:?}", private), "PrivateKey(<redacted>)");
         assert_eq!(public.len(), 40);
         assert!(!public.starts_with("0x"));
     }
@@ -54,18 +88,20 @@ mod tests {
     #[test]
     fn test_generate_contract_address() {
         let wallet = eth::Wallet {
-            private_key: "".to_string(),
+            private_key: eth::PrivateKey::from_bytes([0; 32]),
             public_key: "Ff4a7BE855756B282c211eEfb5e1c5b0c71abDF4".to_string(),
         };
         let contract_address = eth::generate_contract_address(&wallet);
 
         println!(
             "{} {} {}",
-            &wallet.private_key, &wallet.public_key, contract_address
+            wallet.private_key.to_hex().as_str(),
+            &wallet.public_key,
+            contract_address
         );
 
         let wallet = eth::Wallet {
-            private_key: "".to_string(),
+            private_key: eth::PrivateKey::from_bytes([0; 32]),
             public_key: "a6E4dC8578C80ba05E531dB19aA88cd3Bc7F76A5".to_string(),
         };
         let contract_address = eth::generate_contract_address(&wallet);
@@ -76,6 +112,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_generate_contract_address_at_nonce() {
+        let wallet = eth::Wallet {
+            private_key: eth::PrivateKey::from_bytes([0; 32]),
+            public_key: "6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0".to_string(),
+        };
+        let expected = [
+            "cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d",
+            "343c43a37d37dff08ae8c4a11544c718abb4fcf8",
+            "f778b86fa74e846c4f0a1fbd1335fe81c00a0c91",
+            "fffd933a0bc612844eaf0c6fe3e5b8e9b6c1d19c",
+        ];
+        for (nonce, address) in expected.iter().enumerate() {
+            assert_eq!(
+                eth::generate_contract_address_at(&wallet, nonce as u64),
+                *address
+            );
+        }
+        assert_eq!(eth::generate_contract_address(&wallet), expected[0]);
+    }
+
+    #[test]
+    fn test_parse_nonce_range() {
+        use conf::config::parse_nonce_range;
+        assert_eq!(parse_nonce_range("0..5").unwrap(), 0..5);
+        assert_eq!(parse_nonce_range("3..=3").unwrap(), 3..4);
+        assert!(parse_nonce_range("5..5").is_err());
+        assert!(parse_nonce_range("a..b").is_err());
+    }
+
     #[test]
     fn test_checksum() {
         let addr_lowercase = "e0fc04fa2d34a66b779fd5cee748268032a146c0";
@@ -86,4 +152,108 @@ mod tests {
         let checksummed = eth::checksum(addr_uppercase);
         assert_eq!(checksummed, "e0FC04FA2d34a66B779fd5CEe748268032a146c0");
     }
+
+    #[test]
+    fn test_config_errors() {
+        use conf::config::parse_command;
+        let exit_code = |args: &[&str]| match parse_command(args) {
+            Ok(_) => 0,
+            Err(e) => e.exit_code(),
+        };
+        assert_eq!(
+            exit_code(&["ranityeth", "eoa", "-s", "startswith", "-p", "a"]),
+            0
+        );
+        assert_eq!(exit_code(&["ranityeth", "eoa", "-s", "startswith"]), 2);
+        assert_eq!(
+            exit_code(&["ranityeth", "eoa", "-s", "trailing", "-p", "ab"]),
+            2
+        );
+        assert_eq!(exit_code(&["ranityeth", "eoa", "-s", "hook", "-p", "a"]), 2);
+        let eoa = ["ranityeth", "eoa", "-s", "startswith", "-p", "a"];
+        assert_eq!(
+            exit_code(&[&eoa[..], &["--max-file-size", "10M"]].concat()),
+            0
+        );
+        assert_eq!(
+            exit_code(&[&eoa[..], &["--max-file-size", "0"]].concat()),
+            2
+        );
+        assert_eq!(
+            exit_code(&[&eoa[..], &["--max-file-size", "1T"]].concat()),
+            2
+        );
+        assert_eq!(
+            exit_code(&["ranityeth", "eoa", "-s", "bogus", "-p", "a"]),
+            2
+        );
+        let long_pattern = "a".repeat(41);
+        assert_eq!(
+            exit_code(&["ranityeth", "eoa", "-s", "startswith", "-p", &long_pattern]),
+            2
+        );
+        let create2 = [
+            "ranityeth",
+            "create2",
+            "-s",
+            "contains",
+            "-p",
+            "a",
+            "--deployer",
+        ];
+        let bytecode = "60".repeat(32);
+        assert_eq!(
+            exit_code(&[&create2[..], &["0x12", "--bytecode", &bytecode]].concat()),
+            65
+        );
+        let deployer = "4e59b44847b379578588920ca78fbf26c0b4956c";
+        assert_eq!(
+            exit_code(&[&create2[..], &[deployer, "--bytecode", "zz"]].concat()),
+            65
+        );
+        assert_eq!(
+            exit_code(&[&create2[..], &[deployer, "--bytecode", &bytecode]].concat()),
+            0
+        );
+    }
+
+    #[test]
+    fn test_run_checks_salt_prefix() {
+        // a config built without the parser, with no room left for the salt counter
+        let config = conf::config::AppConfig {
+            contract: true,
+            create2: true,
+            salt_prefix: vec![0xab; 25],
+            ..conf::config::AppConfig::with_pattern(
+                "a".to_string(),
+                strategy::Strategy::Startswith,
+                false,
+            )
+        };
+        assert_eq!(thread::run(config).unwrap_err().exit_code(), 2);
+    }
+
+    #[test]
+    fn test_startswith_longer_than_address() {
+        let address = "dead000000000000000000000000000000000000";
+        let config = match conf::config::parse_command([
+            "ranityeth",
+            "eoa",
+            "-s",
+            "startswith",
+            "-p",
+            "dead",
+        ]) {
+            Ok(conf::config::Command::Search(config)) => config,
+            _ => panic!("Expected a search"),
+        };
+        let config = conf::config::AppConfig {
+            pattern: format!("{}00", address),
+            ..*config
+        };
+        assert_eq!(
+            strategy::Score::score(&strategy::Strategy::Startswith, &config, address),
+            40
+        );
+    }
 }
@@ -1,10 +1,17 @@
 use crate::conf::config::AppConfig;
+use crate::create2::Create2Context;
+use crate::entropy::KeyRng;
+use crate::error::Error;
 use crate::eth::Wallet;
 use crate::eth::{self, checksum};
-use crate::fs::append_to_file;
+use crate::hd::{MnemonicKey, MnemonicSearch};
+use crate::output::Record;
+use crate::report::{self, Leaderboard, Reporter, Threshold};
+use crate::splitkey::SplitKeySearch;
 use crate::strategy::{Score, Strategy};
-use crate::{create2, utils};
+use crate::{create2, sched, utils, verify};
 use std::io::Write;
+use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc;
 use std::sync::mpsc::Sender;
@@ -12,192 +19,480 @@ use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
+/// A verified result sent from a worker to the main thread, `kept` when it is reported and
+/// not only a candidate for the leaderboard.
+pub struct Found {
+    pub record: Record,
+    pub kept: bool,
+}
+
+// Count a worker reaches between publishing it and checking for cancellation, so that
+// shared counters are touched every few milliseconds instead of on every address. The count
+// goes up by CANDIDATES per iteration, or by one per mnemonic.
+fn batch_size(config: &AppConfig) -> u64 {
+    if config.mnemonic.is_some() {
+        1
+    } else if config.create2 {
+        4096
+    } else {
+        64
+    }
+}
+
+// Candidates computed per iteration, hashed together by the multi-lane Keccak.
+const CANDIDATES: usize = 8;
+
+// An address a worker scored, with what is needed to report it.
+struct Candidate {
+    wallet: Option<Rc<Wallet>>,
+    salt: [u8; 32],
+    nonce: u64,
+    address: String,
+    mnemonic: Option<MnemonicKey>,
+    score: u64,
+}
+
 pub fn find_address_starting_with(
     found: Arc<AtomicBool>,
     processed: Arc<AtomicU64>,
     config: AppConfig,
     best_score: Arc<AtomicU64>,
-) -> Wallet {
-    let mut salt = create2::generate_salt(); // used for create2
-    let mut wallet = Wallet::new();
-    let strategy = &config.strategy;
-    let bytecode_hash = create2::bytecode_keccak(&config.bytecode);
+    top_floor: Arc<AtomicU64>,
+    tx: Sender<Found>,
+) {
+    let mut rng = config.entropy.rng();
+    // create2 results have no key, the record ignores the wallet
+    let placeholder = Wallet::new();
+    let mut create2_context = config.create2.then(|| {
+        let salt =
+            create2::apply_salt_prefix(create2::generate_salt(&mut rng), &config.salt_prefix);
+        Create2Context::from_config(&config, salt, CANDIDATES)
+    });
+    let eoa_config = config.eoa_config();
+    let threshold = Threshold::new(&config);
+    let mut split_key_search = config
+        .split_key
+        .as_ref()
+        .map(|public_key| SplitKeySearch::new(public_key, &mut rng));
+    let mut mnemonic_search = config.mnemonic.as_ref().map(MnemonicSearch::new);
+    let batch = batch_size(&config);
+    let mut count = 0;
+    // last seen value of `best_score`, refreshed every batch and on every improvement
+    let mut best = 0;
     loop {
-        if found.load(Ordering::Relaxed) {
-            return Wallet::new();
-        }
-
-        let mut address;
-
-        if config.create2 {
-            salt = create2::derive_salt(salt);
-            address = create2::calc_addr(config.deployer.as_str(), salt, bytecode_hash);
-        } else {
-            wallet = Wallet::new();
-            address = match config.contract {
-                false => wallet.public_key.clone(),
-                true => eth::generate_contract_address(&wallet),
-            };
+        if count >= batch {
+            processed.fetch_add(count, Ordering::Relaxed);
+            count = 0;
+            if found.load(Ordering::Relaxed) {
+                return;
+            }
+            best = best_score.load(Ordering::Relaxed);
         }
 
-        address = match config.casesensitive {
-            false => address,
-            true => checksum(&address),
-        };
-
-        let _score = strategy.score(&config, &address);
-        match strategy {
-            Strategy::Contains => {
-                if _score == 1 {
-                    if !config.continuous {
-                        write_wallet_info(&wallet, &config, salt, _score);
-                        found.store(true, Ordering::Relaxed);
-                        return wallet;
-                    } else {
-                        _ = append_to_file(
-                            config.pkstorage_path.as_str(),
-                            format!("{}\n", wallet.private_key).as_str(),
-                        );
-                    }
+        let candidates: Vec<Candidate> = if let Some(context) = &mut create2_context {
+            context.next_batch();
+            // addresses are scored in a reused hex buffer, only the ones that may be reported
+            // become candidates
+            let mut buffer = [0u8; 40];
+            let mut candidates = vec![];
+            for (i, address) in context.addresses().iter().enumerate() {
+                hex::encode_to_slice(address, &mut buffer).expect("Buffer fits an address");
+                let address = std::str::from_utf8(&buffer).expect("Hex is ASCII");
+                let score = score_address(&config, address);
+                let ranked = report::rank(score, address) > top_floor.load(Ordering::Relaxed);
+                if threshold.keeps(score, best) || ranked {
+                    candidates.push(Candidate {
+                        wallet: None,
+                        salt: context.salt(i),
+                        nonce: config.nonces.start,
+                        address: address.to_string(),
+                        mnemonic: None,
+                        score,
+                    });
                 }
             }
-            Strategy::Startswith => {
-                if _score > best_score.load(Ordering::Relaxed) || config.continuous {
-                    if !config.continuous {
-                        best_score.store(_score, Ordering::Relaxed);
-                        write_wallet_info(&wallet, &config, salt, _score);
-                    }
-
-                    if _score == config.pattern.len() as u64 {
-                        if !config.continuous {
-                            found.store(true, Ordering::Relaxed);
-                            return wallet;
-                        } else {
-                            _ = append_to_file(
-                                config.pkstorage_path.as_str(),
-                                format!("{}\n", wallet.private_key).as_str(),
-                            );
-                        }
+            candidates
+        } else if config.contract {
+            let wallets = next_wallets(&mut split_key_search, &mut rng);
+            // both the deployer and its contract have to match
+            let eoa_scores: Vec<u64> = wallets
+                .iter()
+                .map(|wallet| match &eoa_config {
+                    Some(eoa_config) => score_address(eoa_config, &wallet.public_key),
+                    None => 0,
+                })
+                .collect();
+            // every nonce kept on its own, and the best scoring nonce of every wallet for
+            // improvements and the leaderboard
+            let mut hits: Vec<(usize, u64, u64, String)> = vec![];
+            let mut best_nonces: Vec<Option<(u64, u64, String)>> = vec![None; wallets.len()];
+            for n in config.nonces.clone() {
+                let addresses = eth::generate_contract_addresses_at(&wallets, n);
+                for (i, address) in addresses.into_iter().enumerate() {
+                    let score = score_address(&config, &address) + eoa_scores[i];
+                    if !threshold.tracks_best() && threshold.keeps(score, best) {
+                        hits.push((i, score, n, address));
+                    } else if best_nonces[i]
+                        .as_ref()
+                        .is_none_or(|(best, _, _)| score > *best)
+                    {
+                        best_nonces[i] = Some((score, n, address));
                     }
                 }
             }
-            Strategy::Trailing => {
-                // config.pattern should be one character
-                // count how many consecutive characters are at the beginning of the address
-                if _score > best_score.load(Ordering::Relaxed) {
-                    best_score.store(_score, Ordering::Relaxed);
-                    write_wallet_info(&wallet, &config, salt, _score)
+            let wallets: Vec<Rc<Wallet>> = wallets.into_iter().map(Rc::new).collect();
+            let best_nonces = best_nonces
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, best_nonce)| best_nonce.map(|(s, n, a)| (i, s, n, a)));
+            hits.into_iter()
+                .chain(best_nonces)
+                .map(|(i, score, nonce, address)| Candidate {
+                    wallet: Some(wallets[i].clone()),
+                    salt: [0; 32],
+                    nonce,
+                    address,
+                    mnemonic: None,
+                    score,
+                })
+                .collect()
+        } else if let Some(search) = &mut mnemonic_search {
+            search.next_mnemonic(&mut rng);
+            // every index kept on its own, and the best scoring index of the mnemonic for
+            // improvements and the leaderboard
+            let mut hits: Vec<(u64, u32, Wallet)> = vec![];
+            let mut best_index: Option<(u64, u32, Wallet)> = None;
+            for index in search.indexes() {
+                if let Some(wallet) = search.wallet_at(index) {
+                    let score = score_address(&config, &wallet.public_key);
+                    if !threshold.tracks_best() && threshold.keeps(score, best) {
+                        hits.push((score, index, wallet));
+                    } else if best_index.as_ref().is_none_or(|(best, _, _)| score > *best) {
+                        best_index = Some((score, index, wallet));
+                    }
                 }
             }
+            hits.into_iter()
+                .chain(best_index)
+                .map(|(score, index, wallet)| Candidate {
+                    address: wallet.public_key.clone(),
+                    wallet: Some(Rc::new(wallet)),
+                    salt: [0; 32],
+                    nonce: config.nonces.start,
+                    mnemonic: Some(search.key_at(index)),
+                    score,
+                })
+                .collect()
+        } else {
+            next_wallets(&mut split_key_search, &mut rng)
+                .into_iter()
+                .map(|wallet| Candidate {
+                    address: wallet.public_key.clone(),
+                    score: score_address(&config, &wallet.public_key),
+                    wallet: Some(Rc::new(wallet)),
+                    salt: [0; 32],
+                    nonce: config.nonces.start,
+                    mnemonic: None,
+                })
+                .collect()
+        };
+        // a mnemonic counts once however many of its indexes are candidates, a wallet
+        // matching at several nonces once and create2 addresses that are not candidates as well
+        count += match (config.contract || config.create2, &mnemonic_search) {
+            (true, _) => CANDIDATES,
+            (false, Some(_)) => 1,
+            (false, None) => candidates.len(),
+        } as u64;
+
+        for candidate in candidates {
+            let score = candidate.score;
+            let mut kept = threshold.keeps(score, best);
+            if kept && threshold.tracks_best() {
+                // another thread may have reported a better address since the last batch
+                let previous = best_score.fetch_max(score, Ordering::Relaxed);
+                best = previous.max(score);
+                kept = previous < score;
+            }
+            let ranked =
+                report::rank(score, &candidate.address) > top_floor.load(Ordering::Relaxed);
+            if !kept && !ranked {
+                continue;
+            }
+            let hit = Hit {
+                wallet: candidate.wallet.as_deref().unwrap_or(&placeholder),
+                salt: candidate.salt,
+                nonce: candidate.nonce,
+                address: &candidate.address,
+                mnemonic: candidate.mnemonic.as_ref(),
+                score,
+            };
+            let Some(record) = new_record(&hit, &config) else {
+                continue;
+            };
+            let complete = kept && threshold.ends(score);
+            if complete {
+                found.store(true, Ordering::Relaxed);
+            }
+            if tx.send(Found { record, kept }).is_err() || complete {
+                processed.fetch_add(count, Ordering::Relaxed);
+                return;
+            }
         }
+    }
+}
+
+fn next_wallets(split_key_search: &mut Option<SplitKeySearch>, rng: &mut KeyRng) -> Vec<Wallet> {
+    match split_key_search {
+        Some(search) => search.next_wallets(CANDIDATES),
+        None => eth::generate_wallets(rng, CANDIDATES),
+    }
+}
+
+/// Score at which a result is complete, summed over the contract and deployer patterns.
+pub fn target_score(config: &AppConfig) -> u64 {
+    let target = config.strategy.target_score(config);
+    match config.eoa_config() {
+        Some(eoa_config) => target.saturating_add(eoa_config.strategy.target_score(&eoa_config)),
+        None => target,
+    }
+}
+
+/// Expected number of addresses to try until one is a complete match.
+pub fn expected_attempts(config: &AppConfig) -> u64 {
+    let mut difficulty = utils::calculate_difficulty(&config.pattern, config.casesensitive);
+    if config.strategy == Strategy::Hook {
+        difficulty = difficulty.saturating_mul(1 << 14);
+    }
+    if let Some(eoa_pattern) = &config.eoa_pattern {
+        difficulty = difficulty.saturating_mul(utils::calculate_difficulty(
+            eoa_pattern,
+            config.casesensitive,
+        ));
+    }
+    difficulty
+}
 
-        processed.fetch_add(1, Ordering::Relaxed);
+/// Score of a lowercase `address`, checksummed first for case sensitive searches.
+pub(crate) fn score_address(config: &AppConfig, address: &str) -> u64 {
+    match config.casesensitive {
+        false => config.strategy.score(config, address),
+        true => config.strategy.score(config, &checksum(address)),
     }
 }
 
 pub fn spawn_threads(
     config: &AppConfig,
-    tx: &Sender<Wallet>,
+    tx: &Sender<Found>,
     found: &Arc<AtomicBool>,
     processed: &Arc<AtomicU64>,
-) -> Vec<thread::JoinHandle<Result<(), mpsc::SendError<Wallet>>>> {
-    println!("Starting generation with {} threads.", config.threads);
+    top_floor: &Arc<AtomicU64>,
+) -> Vec<thread::JoinHandle<()>> {
+    if !config.quiet {
+        eprintln!("Starting generation with {} threads.", config.threads);
+    }
     let mut threads = vec![];
     let best_score = Arc::new(AtomicU64::new(0));
+    let cores = match config.pin_cores {
+        true => sched::allowed_cores().unwrap_or_default(),
+        false => vec![],
+    };
 
-    for _ in 0..config.threads {
+    for i in 0..config.threads as usize {
         let thread_tx = tx.clone();
         let config_clone = config.clone();
         let found_clone = found.clone();
         let processed_clone = processed.clone();
         let best_score_clone = best_score.clone();
+        let top_floor_clone = top_floor.clone();
+
+        // with more threads than cores, threads share cores round robin
+        let core = (!cores.is_empty()).then(|| cores[i % cores.len()]);
 
         threads.push(thread::spawn(move || {
-            thread_tx.send(find_address_starting_with(
+            if let Some(core) = core {
+                if let Err(e) = sched::pin_to_core(core) {
+                    eprintln!("Unable to pin thread to core {}: {}", core, e);
+                }
+            }
+            find_address_starting_with(
                 found_clone,
                 processed_clone,
                 config_clone,
                 best_score_clone,
-            ))
+                top_floor_clone,
+                thread_tx,
+            )
         }))
     }
 
     threads
 }
 
-pub fn write_wallet_info(wallet: &Wallet, config: &AppConfig, salt: [u8; 32], score: u64) {
-    print!("--------------\n");
-    println!("SCORE: {}", score);
-    if config.create2 {
-        let salt_hex = hex::encode(&salt);
-        println!("Found salt: {}", salt_hex);
-    } else {
-        println!("Private key: {}", wallet.private_key);
-        println!("Address: 0x{}", checksum(&wallet.public_key));
-    }
-    if config.contract {
-        let contract_address;
-        if config.create2 {
-            contract_address = create2::calc_addr(
-                config.deployer.as_str(),
-                salt,
-                create2::bytecode_keccak(&config.bytecode),
-            );
-        } else {
-            contract_address = eth::generate_contract_address(&wallet);
-        }
-        println!("Contract address: 0x{}", checksum(&contract_address));
+/// A scored candidate, with `address` being the address the score was computed on (the
+/// contract address for contract and create2 searches).
+pub struct Hit<'a> {
+    pub wallet: &'a Wallet,
+    pub salt: [u8; 32],
+    pub nonce: u64,
+    pub address: &'a str,
+    pub mnemonic: Option<&'a MnemonicKey>,
+    pub score: u64,
+}
+
+// Builds the output record of a hit and verifies it independently.
+fn new_record(hit: &Hit, config: &AppConfig) -> Option<Record> {
+    let mut record = Record::new(
+        hit.wallet,
+        config,
+        hit.salt,
+        hit.nonce,
+        hit.address,
+        hit.score,
+    );
+    if let Some(mnemonic) = hit.mnemonic {
+        record.mnemonic = Some(mnemonic.phrase.clone());
+        record.derivation_path = Some(mnemonic.path.clone());
+    }
+    if let Err(e) = verify::verify_record(&record, config) {
+        eprintln!("Refusing to report unverified result: {}", e);
+        return None;
     }
-    print!("--------------\n\n\n");
+    Some(record)
 }
 
-pub fn run(config: AppConfig) {
-    let mut privatkey_list: Vec<String> = Vec::new();
-    for _ in 0..10000 {
-        let acc = eth::Wallet::new();
-        privatkey_list.push(acc.private_key);
+pub fn run(config: AppConfig) -> Result<(), Error> {
+    // configs built without the parser have not been normalized
+    let pattern = utils::parse_pattern(&config.pattern, &config.strategy, config.casesensitive)?;
+    if pattern != config.pattern {
+        return Err(Error::Config(format!(
+            "Pattern is not normalized, expected {}",
+            pattern
+        )));
     }
+    create2::check_salt_prefix(&config.salt_prefix)?;
 
-    if !utils::is_possible_pattern(&config.pattern) {
-        println!("Impossible pattern. Use 0-9, a-f");
-        return;
+    let threshold = Threshold::new(&config);
+    if let (Some(min_score), Some(complete)) = (config.min_score, threshold.complete) {
+        if min_score > complete {
+            return Err(Error::Config(format!(
+                "--min-score {} is above the score of a complete match ({})",
+                min_score, complete
+            )));
+        }
+    }
+
+    if config.nice {
+        sched::lower_priority()?;
+    }
+    if config.pin_cores {
+        sched::allowed_cores()?;
+    }
+
+    let mut reporter = Reporter::new(&config)?;
+    let mut leaderboard = config.top.map(Leaderboard::new);
+    if leaderboard.is_some() {
+        if let Err(e) = sched::catch_interrupt() {
+            eprintln!(
+                "Unable to catch Ctrl-C, the leaderboard is only shown at the end: {}",
+                e
+            );
+        }
     }
 
     let (tx, rx) = mpsc::channel();
     let found = Arc::new(AtomicBool::new(false));
     let processed = Arc::new(AtomicU64::new(0));
+    // without a leaderboard no rank is above the floor
+    let top_floor = Arc::new(AtomicU64::new(match leaderboard {
+        Some(_) => 0,
+        None => u64::MAX,
+    }));
 
-    let threads = spawn_threads(&config, &tx, &found, &processed);
+    let threads = spawn_threads(&config, &tx, &found, &processed, &top_floor);
+    // the channel disconnects once every worker has returned
+    drop(tx);
 
     let start_time = Instant::now();
     let mut last_generated = 0;
+    let mut next_progress = start_time + Duration::from_secs(1);
     loop {
-        if let Ok(_wallet) = rx.recv_timeout(Duration::from_millis(1000)) {
-            if found.load(Ordering::Relaxed) {
-                break;
+        // checked on every result as well, results may keep arriving without a pause
+        if sched::interrupted() {
+            break;
+        }
+        let timeout = next_progress.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(timeout) {
+            Ok(Found { mut record, kept }) => {
+                // reported first, which replaces the private key with its keystore
+                let done = kept && reporter.report(&mut record);
+                if let Some(leaderboard) = &mut leaderboard {
+                    leaderboard.offer(&record);
+                    top_floor.store(leaderboard.floor(), Ordering::Relaxed);
+                }
+                if done {
+                    break;
+                }
             }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        if Instant::now() < next_progress {
+            continue;
         }
+        next_progress += Duration::from_secs(1);
 
         let elapsed = start_time.elapsed().as_secs();
         let generated = processed.load(Ordering::Relaxed);
         let speed = generated - last_generated;
         last_generated = generated;
 
-        let difficulty = utils::calculate_difficulty(&config.pattern, config.casesensitive);
+        let mut difficulty = expected_attempts(&config);
+        // every mnemonic is scored on all of its address indexes and every deployer key at
+        // all nonces of --nonce-range, those are what is counted
+        let nonces = config.nonces.end.saturating_sub(config.nonces.start);
+        let per_count = match &config.mnemonic {
+            Some(mnemonic) => Some(("mnemonics", mnemonic.indexes.len() as u64)),
+            None if config.contract && !config.create2 && nonces > 1 => Some(("keys", nonces)),
+            None => None,
+        };
+        if let Some((_, addresses)) = per_count {
+            difficulty = difficulty.div_ceil(addresses);
+        }
         let estimated_time: u64 = utils::calculate_estimated_time(speed, difficulty);
 
         let time_left = utils::time_left(estimated_time, elapsed);
 
-        if config.strategy != Strategy::Trailing {
-            print!(
-                "\r Speed: {} h/s. Up-time: {}s. Max time left: {}s. Generated {} addresses",
-                speed, elapsed, time_left, generated
+        // progress goes to stderr, stdout only carries results
+        if !config.quiet {
+            let speed = match per_count {
+                Some((unit, addresses)) => {
+                    format!("{} {}/s ({} addresses/s)", speed, unit, speed * addresses)
+                }
+                None => format!("{} h/s", speed),
+            };
+            // without a complete match there is no time left to estimate
+            let left = match reporter.threshold.complete {
+                Some(_) => format!("Max time left: {}s", time_left),
+                None => format!("Best score: {}", reporter.best),
+            };
+            let unit = per_count.map_or("addresses", |(unit, _)| unit);
+            eprint!(
+                "\r Speed: {}. Up-time: {}s. {}. Generated {} {}",
+                speed, elapsed, left, generated, unit
             );
-            _ = std::io::stdout().flush();
+            _ = std::io::stderr().flush();
         }
     }
 
+    // stops the workers when --max-results or Ctrl-C ended the search
+    found.store(true, Ordering::Relaxed);
     for t in threads {
         _ = t.join();
     }
+
+    if let Some(leaderboard) = leaderboard {
+        reporter.finish(leaderboard);
+    }
+    reporter.close();
+    Ok(())
 }
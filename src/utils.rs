@@ -1,23 +1,79 @@
+use crate::error::Error;
+use crate::strategy::Strategy;
+
 pub fn is_possible_pattern(x: &str) -> bool {
     x.as_bytes()
         .iter()
-        .all(|&c| (b'a'..=b'f').contains(&c) || (b'0'..=b'9').contains(&c))
+        .all(|&c| (b'a'..=b'f').contains(&c) || c.is_ascii_digit())
 }
 
-pub fn calculate_difficulty(s: &str, case_sensitive: bool) -> u64 {
-    if case_sensitive {
-        22_u64.pow(s.len() as u32)
-    } else {
-        16_u64.pow(s.len() as u32)
+/// Normalizes a pattern given on the command line for `strategy`: strips a `0x` prefix and
+/// lowercases it unless the search is case sensitive. Returns why no address can match it
+/// otherwise.
+pub fn parse_pattern(
+    pattern: &str,
+    strategy: &Strategy,
+    case_sensitive: bool,
+) -> Result<String, Error> {
+    let invalid = |message: String| Err(Error::Config(message));
+    let pattern = pattern
+        .strip_prefix("0x")
+        .or_else(|| pattern.strip_prefix("0X"))
+        .unwrap_or(pattern);
+    if pattern.is_empty() && *strategy != Strategy::Hook {
+        return invalid("Pattern cannot be empty".to_string());
+    }
+    if let Some((i, c)) = pattern.char_indices().find(|(_, c)| !c.is_ascii_hexdigit()) {
+        return invalid(format!(
+            "Pattern contains '{}' at position {}, addresses only contain 0-9 and a-f",
+            c,
+            i + 1
+        ));
+    }
+
+    match strategy {
+        Strategy::Trailing if pattern.len() != 1 => {
+            return invalid("Trailing strategy only accepts a single character pattern".to_string())
+        }
+        // the last 14 bits of a hook address are its permission flags
+        Strategy::Hook if pattern.len() > 36 => {
+            return invalid(format!(
+                "Pattern is {} characters long, hook addresses only have 36 before the flags",
+                pattern.len()
+            ))
+        }
+        _ if pattern.len() > 40 => {
+            return invalid(format!(
+                "Pattern is {} characters long, an address only has 40",
+                pattern.len()
+            ))
+        }
+        _ => {}
+    }
+
+    // EIP-55 uppercases a letter when the matching nibble of the hash of the lowercase
+    // address is 8 or more, so every letter can take either case at any position and only
+    // the letters can be case sensitive.
+    match case_sensitive {
+        true => Ok(pattern.to_string()),
+        false => Ok(pattern.to_ascii_lowercase()),
     }
 }
 
+/// Expected number of addresses until one has `s` at a given position. Under EIP-55 a
+/// letter of a given case is half as likely as a digit.
+pub fn calculate_difficulty(s: &str, case_sensitive: bool) -> u64 {
+    s.chars().fold(1_u64, |difficulty, c| {
+        let odds = match case_sensitive && c.is_ascii_alphabetic() {
+            true => 32,
+            false => 16,
+        };
+        difficulty.saturating_mul(odds)
+    })
+}
+
 pub fn calculate_estimated_time(speed: u64, difficulty: u64) -> u64 {
-    if speed == 0 {
-        0
-    } else {
-        difficulty / speed
-    }
+    difficulty.checked_div(speed).unwrap_or(0)
 }
 
 pub fn time_left(estimated_time: u64, elapsed_time: u64) -> u64 {
@@ -0,0 +1,397 @@
+// Independent re-check of every result before it is reported. Nothing in here reuses the
+// search code path: hashing goes through the `sha3` crate instead of `tiny-keccak`, RLP and
+// EIP-55 are encoded by hand, mnemonics are checked and derived by a BIP-39 and BIP-32
+// implementation of its own instead of the `bip39` crate and `hd`, and scores are recomputed
+// from the record's own fields.
+use crate::conf::config::AppConfig;
+use crate::eth;
+use crate::output::Record;
+use crate::strategy::Strategy;
+use bip39::Language;
+use hmac::{Hmac, Mac};
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use sha2::{Sha256, Sha512};
+use sha3::{Digest, Keccak256};
+use zeroize::Zeroizing;
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+fn decode_hex(value: &str, what: &str) -> Result<Vec<u8>, String> {
+    hex::decode(value.trim_start_matches("0x")).map_err(|_| format!("{} is not valid hex", what))
+}
+
+fn address_of(public_key: &PublicKey) -> String {
+    hex::encode(&keccak256(&public_key.serialize_uncompressed()[1..])[12..])
+}
+
+/// EIP-55 mixed-case encoding of a lowercase hex address.
+fn eip55(address: &str) -> String {
+    let hash = keccak256(address.as_bytes());
+    address
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Checks that `reported` is the `0x` prefixed, correctly checksummed form of `expected`.
+fn check_address(reported: &Option<String>, expected: &str, what: &str) -> Result<(), String> {
+    let reported = reported
+        .as_deref()
+        .ok_or_else(|| format!("{} is missing", what))?;
+    let reported = reported
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("{} is not 0x prefixed", what))?;
+    if reported.to_lowercase() != expected {
+        return Err(format!(
+            "{} 0x{} does not match the recomputed 0x{}",
+            what, reported, expected
+        ));
+    }
+    if reported != eip55(expected) {
+        return Err(format!("{} 0x{} has an invalid checksum", what, reported));
+    }
+    Ok(())
+}
+
+fn rlp_contract_address(sender: &[u8], nonce: u64) -> String {
+    let mut payload = vec![0x80 + sender.len() as u8];
+    payload.extend_from_slice(sender);
+    match nonce {
+        0 => payload.push(0x80),
+        1..=0x7f => payload.push(nonce as u8),
+        _ => {
+            let bytes = nonce.to_be_bytes();
+            let skip = bytes.iter().take_while(|&&b| b == 0).count();
+            payload.push(0x80 + (8 - skip) as u8);
+            payload.extend_from_slice(&bytes[skip..]);
+        }
+    }
+    let mut encoded = vec![0xc0 + payload.len() as u8];
+    encoded.extend(payload);
+    hex::encode(&keccak256(&encoded)[12..])
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> Zeroizing<[u8; 64]> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes any key");
+    for data in data {
+        mac.update(data);
+    }
+    let mut output = Zeroizing::new([0u8; 64]);
+    output.copy_from_slice(&mac.finalize().into_bytes());
+    output
+}
+
+/// BIP-39 seed of an English `phrase` without passphrase, once its checksum checks out.
+fn mnemonic_seed(phrase: &str) -> Result<Zeroizing<[u8; 64]>, String> {
+    let words: Vec<&str> = phrase.split(' ').collect();
+    if ![12, 15, 18, 21, 24].contains(&words.len()) {
+        return Err(format!("Mnemonic has {} words", words.len()));
+    }
+    // 11 bits per word: the entropy followed by the first bits of its SHA-256
+    let mut bits = Zeroizing::new(Vec::with_capacity(words.len() * 11));
+    for word in words {
+        let index = Language::English
+            .find_word(word)
+            .ok_or("Mnemonic has a word outside the BIP-39 word list")?;
+        bits.extend((0..11).rev().map(|bit| (index >> bit) as u8 & 1));
+    }
+    let (entropy_bits, checksum) = bits.split_at(bits.len() / 33 * 32);
+    let entropy: Zeroizing<Vec<u8>> = Zeroizing::new(
+        entropy_bits
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0, |acc, bit| acc << 1 | bit))
+            .collect(),
+    );
+    let hash = Sha256::digest(&*entropy);
+    let valid = checksum
+        .iter()
+        .enumerate()
+        .all(|(i, &bit)| hash[i / 8] >> (7 - i % 8) & 1 == bit);
+    if !valid {
+        return Err("Mnemonic checksum is invalid".to_string());
+    }
+    let mut seed = Zeroizing::new([0u8; 64]);
+    pbkdf2::pbkdf2_hmac::<Sha512>(phrase.as_bytes(), b"mnemonic", 2048, &mut *seed);
+    Ok(seed)
+}
+
+/// BIP-32 private key at `path`, e.g. `m/44'/60'/0'/0/3`, derived from `seed`.
+fn derive_key(seed: &[u8], path: &str) -> Result<Zeroizing<[u8; 32]>, String> {
+    let context = Secp256k1::new();
+    let invalid = || format!("Invalid derivation path {}", path);
+    let parts = path.strip_prefix("m/").ok_or_else(invalid)?.split('/');
+    // the key of the current node and, in the second half, its chain code
+    let mut output = hmac_sha512(b"Bitcoin seed", &[seed]);
+    let mut key = SecretKey::from_slice(&output[..32]).map_err(|_| invalid())?;
+    for part in parts {
+        let (index, hardened) = match part.strip_suffix('\'') {
+            Some(index) => (index, true),
+            None => (part, false),
+        };
+        let index: u32 = index.parse().map_err(|_| invalid())?;
+        if index >= 1 << 31 {
+            return Err(invalid());
+        }
+        // hardened children hash the private key, normal children the compressed public key
+        let chain_code = &output[32..];
+        let child_output = match hardened {
+            true => hmac_sha512(
+                chain_code,
+                &[&[0], &key[..], &(index | 1 << 31).to_be_bytes()],
+            ),
+            false => hmac_sha512(
+                chain_code,
+                &[
+                    &PublicKey::from_secret_key(&context, &key).serialize(),
+                    &index.to_be_bytes(),
+                ],
+            ),
+        };
+        let mut child = SecretKey::from_slice(&child_output[..32]).map_err(|_| invalid())?;
+        let valid = child.add_assign(&key[..]).is_ok();
+        eth::erase_secret_key(&mut key);
+        if !valid {
+            eth::erase_secret_key(&mut child);
+            return Err(invalid());
+        }
+        key = child;
+        output = child_output;
+    }
+    let mut derived = Zeroizing::new([0u8; 32]);
+    derived.copy_from_slice(&key[..]);
+    eth::erase_secret_key(&mut key);
+    Ok(derived)
+}
+
+fn score(strategy: &Strategy, pattern: &str, hook_flags: u16, address: &str) -> u64 {
+    let matching = || {
+        address
+            .chars()
+            .zip(pattern.chars())
+            .filter(|(a, p)| a == p)
+            .count() as u64
+    };
+    match strategy {
+        Strategy::Startswith => matching(),
+        Strategy::Contains => address.contains(pattern) as u64,
+        Strategy::Trailing => {
+            let first = pattern.chars().next();
+            address.chars().take_while(|&c| Some(c) == first).count() as u64
+        }
+        Strategy::Hook => {
+            let bytes = hex::decode(address.to_lowercase()).unwrap_or_default();
+            if bytes.len() != 20
+                || u16::from_be_bytes([bytes[18], bytes[19]]) & 0x3fff != hook_flags
+            {
+                return 0;
+            }
+            1 + matching()
+        }
+    }
+}
+
+/// Recomputes the addresses and the score of `record` from its raw key or CREATE2 inputs and
+/// returns an error if anything differs from what is about to be reported.
+pub fn verify_record(record: &Record, config: &AppConfig) -> Result<(), String> {
+    let account = if config.create2 {
+        let deployer = decode_hex(record.deployer.as_deref().unwrap_or_default(), "Deployer")?;
+        let salt = decode_hex(record.salt.as_deref().unwrap_or_default(), "Salt")?;
+        let init_code_hash = decode_hex(
+            record.init_code_hash.as_deref().unwrap_or_default(),
+            "Init code hash",
+        )?;
+        if deployer[..] != config.deployer[..]
+            || salt.len() != 32
+            || !salt.starts_with(&config.salt_prefix)
+        {
+            return Err("Deployer or salt does not match the search".to_string());
+        }
+        // CREATE3 salts deploy the minimal proxy of solmate and 0xSequence
+        let init_code = match config.create3 {
+            true => decode_hex("67363d3d37363d34f03d5260086018f3", "Proxy bytecode")?,
+            false => config.bytecode.clone(),
+        };
+        if init_code_hash[..] != keccak256(&init_code)[..] {
+            return Err("Init code hash does not match the bytecode".to_string());
+        }
+
+        let mut preimage = vec![0xff];
+        preimage.extend(&deployer);
+        preimage.extend(&salt);
+        preimage.extend(&init_code_hash);
+        let mut contract = hex::encode(&keccak256(&preimage)[12..]);
+        if config.create3 {
+            contract = rlp_contract_address(&keccak256(&preimage)[12..], 1);
+        }
+        check_address(&record.contract_address, &contract, "Contract address")?;
+        None
+    } else {
+        let context = Secp256k1::new();
+        let public_key = match (&record.private_key, &record.key_offset, &config.split_key) {
+            (Some(private_key), None, None) => {
+                let secret_key = SecretKey::from_slice(&decode_hex(private_key, "Private key")?)
+                    .map_err(|_| "Private key is not a valid secp256k1 key".to_string())?;
+                PublicKey::from_secret_key(&context, &secret_key)
+            }
+            (None, Some(offset), Some(split_key)) => {
+                let offset = SecretKey::from_slice(&decode_hex(offset, "Key offset")?)
+                    .map_err(|_| "Key offset is not a valid secp256k1 key".to_string())?;
+                PublicKey::from_secret_key(&context, &offset)
+                    .combine(split_key)
+                    .map_err(|_| "Combined key is invalid".to_string())?
+            }
+            _ => return Err("Record does not hold exactly one key".to_string()),
+        };
+        let account = address_of(&public_key);
+        check_address(&record.address, &account, "Address")?;
+
+        if let Some(mnemonic) = &record.mnemonic {
+            let path = record
+                .derivation_path
+                .as_deref()
+                .ok_or("Derivation path is missing")?;
+            let derived = derive_key(&*mnemonic_seed(mnemonic)?, path)?;
+            let derived = Zeroizing::new(hex::encode(*derived));
+            if record.private_key.as_deref().map(|k| k.as_str()) != Some(derived.as_str()) {
+                return Err(format!(
+                    "Mnemonic does not derive the private key at {}",
+                    path
+                ));
+            }
+        }
+
+        if config.contract {
+            let nonce = record.nonce.ok_or("Nonce is missing")?;
+            let contract = rlp_contract_address(&hex::decode(&account).unwrap_or_default(), nonce);
+            check_address(&record.contract_address, &contract, "Contract address")?;
+        }
+        Some(account)
+    };
+
+    let form = |address: &str| match config.casesensitive {
+        true => eip55(address),
+        false => address.to_string(),
+    };
+    let scored = match (&record.contract_address, &account) {
+        (Some(contract), _) => contract[2..].to_lowercase(),
+        (None, Some(account)) => account.clone(),
+        (None, None) => return Err("Contract address is missing".to_string()),
+    };
+    let mut expected = score(
+        &config.strategy,
+        &config.pattern,
+        config.hook_flags,
+        &form(&scored),
+    );
+    if let (Some(eoa_pattern), Some(account)) = (&config.eoa_pattern, &account) {
+        expected += score(&config.eoa_strategy, eoa_pattern, 0, &form(account));
+    }
+    if expected != record.score {
+        return Err(format!(
+            "Reported score {} does not match the recomputed score {}",
+            record.score, expected
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eip55() {
+        assert_eq!(
+            eip55("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"),
+            "5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        );
+        assert_eq!(
+            eip55("fb6916095ca1df60bb79ce92ce3ea74c37c5d359"),
+            "fB6916095ca1df60bB79Ce92cE3Ea74c37c5d359"
+        );
+    }
+
+    #[test]
+    fn test_rlp_contract_address() {
+        let sender = hex::decode("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0").unwrap();
+        assert_eq!(
+            rlp_contract_address(&sender, 0),
+            "cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d"
+        );
+        assert_eq!(
+            rlp_contract_address(&sender, 3),
+            "fffd933a0bc612844eaf0c6fe3e5b8e9b6c1d19c"
+        );
+        // multi-byte nonces must match the rlp crate used by the search
+        let wallet = crate::eth::Wallet {
+            private_key: crate::eth::PrivateKey::from_bytes([0; 32]),
+            public_key: "6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0".to_string(),
+        };
+        for nonce in [0x7f, 0x80, 0xff, 0x100, 0x1_0000_0000] {
+            assert_eq!(
+                rlp_contract_address(&sender, nonce),
+                crate::eth::generate_contract_address_at(&wallet, nonce)
+            );
+        }
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let seed =
+            mnemonic_seed("test test test test test test test test test test test junk").unwrap();
+        assert_eq!(
+            hex::encode(*derive_key(&*seed, "m/44'/60'/0'/0/0").unwrap()),
+            "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+        );
+        assert_eq!(
+            hex::encode(*derive_key(&*seed, "m/44'/60'/0'/0/1").unwrap()),
+            "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d"
+        );
+        // the same phrase as generated by the search
+        let mut search = crate::hd::MnemonicSearch::new(&crate::hd::MnemonicConfig {
+            words: 15,
+            indexes: 0..1,
+        });
+        search.next_mnemonic(&mut crate::entropy::KeyRng::Os);
+        let key = search.key_at(0);
+        assert_eq!(
+            *derive_key(&*mnemonic_seed(&key.phrase).unwrap(), &key.path).unwrap(),
+            *search.wallet_at(0).unwrap().private_key.as_bytes()
+        );
+        assert!(
+            mnemonic_seed("test test test test test test test test test test test test").is_err()
+        );
+        assert!(mnemonic_seed("test test test").is_err());
+        assert!(derive_key(&*seed, "44'/60'").is_err());
+    }
+
+    #[test]
+    fn test_score() {
+        let address = "dead00000000000000000000000000000000c0c0";
+        assert_eq!(score(&Strategy::Startswith, "dead", 0, address), 4);
+        assert_eq!(score(&Strategy::Startswith, "beef", 0, address), 1);
+        assert_eq!(score(&Strategy::Contains, "c0c0", 0, address), 1);
+        assert_eq!(score(&Strategy::Trailing, "d", 0, address), 1);
+        assert_eq!(score(&Strategy::Hook, "de", 0x80, address), 0);
+        assert_eq!(
+            score(
+                &Strategy::Hook,
+                "de",
+                0x00c0,
+                "dead0000000000000000000000000000000000c0"
+            ),
+            3
+        );
+    }
+}